@@ -0,0 +1,127 @@
+//! Recomputes a lottery's winner from its account data and the Switchboard
+//! randomness account it committed to, using the same `tokenlottery-draw`
+//! functions as `choose_a_winner`.
+//!
+//! Account data can be given as raw bytes or as base64, e.g. the first
+//! element of `data` in `solana account --output json`. Exits with status 1
+//! when the recomputed winner differs from the recorded one.
+
+use std::process::ExitCode;
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::Parser;
+use switchboard_on_demand::{accounts::RandomnessAccountData, Discriminator};
+use tokenlottery::{DrawResult, DRAW_ALGORITHM_UNIFORM, DRAW_ALGORITHM_WEIGHTED};
+use tokenlottery_client::{lottery_address, state};
+
+#[derive(Parser)]
+#[command(name = "verify-draw", about = "Independently recompute a lottery draw")]
+struct Cli {
+    /// `TokenLottery` account data
+    #[arg(long)]
+    lottery: String,
+
+    /// Switchboard randomness account data
+    #[arg(long)]
+    randomness: String,
+
+    /// `EntryWeights` account data, required for weighted lotteries
+    #[arg(long)]
+    entry_weights: Option<String>,
+
+    /// `DrawResult` account data to check against as well
+    #[arg(long)]
+    draw_result: Option<String>,
+
+    /// Files hold base64 text instead of raw bytes
+    #[arg(long)]
+    base64: bool,
+}
+
+fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+    let read = |path: &str| -> Result<Vec<u8>> {
+        let bytes = std::fs::read(path).with_context(|| format!("reading {path}"))?;
+        if cli.base64 {
+            Ok(STANDARD.decode(String::from_utf8(bytes)?.trim())?)
+        } else {
+            Ok(bytes)
+        }
+    };
+
+    let lottery = state::decode_token_lottery(&read(&cli.lottery)?)?;
+    let revealed = revealed_value(&read(&cli.randomness)?)?;
+
+    let (algorithm, winner) = if lottery.is_weighted {
+        let Some(path) = &cli.entry_weights else {
+            bail!("lottery is weighted, pass its entry weights with --entry-weights");
+        };
+        let entry_weights = state::decode_entry_weights(&read(path)?)?;
+        if entry_weights.lottery != lottery_address(&lottery) {
+            bail!("entry weights belong to another lottery");
+        }
+        let weights: Vec<u64> = (0..entry_weights.len).map(|index| entry_weights.weight_of(index)).collect();
+        (DRAW_ALGORITHM_WEIGHTED, tokenlottery_draw::weighted_winner(&revealed, &weights))
+    } else {
+        // sharded lotteries draw an ordinal over their own and then every shard's tickets
+        let ordinal = tokenlottery_draw::uniform_winner(&revealed, lottery.number_of_tickets);
+        (DRAW_ALGORITHM_UNIFORM, ordinal.and_then(|ordinal| lottery.ticket_index_at(ordinal)))
+    };
+
+    println!("lottery: {} (id {})", lottery_address(&lottery), lottery.id);
+    println!("randomness account: {}", lottery.randomness_account);
+    println!("revealed value: {}", hex(&revealed));
+    println!("number of tickets: {}", lottery.number_of_tickets);
+    println!("algorithm: {}", if algorithm == DRAW_ALGORITHM_WEIGHTED { "weighted" } else { "uniform" });
+    let Some(winner) = winner else {
+        bail!("no winner can be drawn, the lottery has no tickets or no weight");
+    };
+    println!("recomputed winner: {winner}");
+
+    let mut matches = true;
+    if lottery.is_winner_chosen {
+        println!("recorded winner: {}", lottery.winner);
+        matches &= lottery.winner == winner;
+    } else {
+        println!("recorded winner: none, the lottery has not been drawn");
+    }
+
+    if let Some(path) = &cli.draw_result {
+        let draw_result = state::decode::<DrawResult>(&read(path)?)?;
+        if draw_result.lottery != lottery_address(&lottery) {
+            bail!("draw result belongs to another lottery");
+        }
+        println!("draw result revealed value: {}", hex(&draw_result.revealed_value));
+        matches &= draw_result.revealed_value == revealed
+            && draw_result.number_of_tickets == lottery.number_of_tickets
+            && draw_result.algorithm_version == algorithm
+            && draw_result.winning_indices == [winner];
+    }
+
+    if matches {
+        println!("OK");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        // the randomness account may have been committed again since the draw,
+        // the draw result keeps the value that was used
+        println!("MISMATCH");
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+fn revealed_value(data: &[u8]) -> Result<[u8; 32]> {
+    let size = std::mem::size_of::<RandomnessAccountData>();
+    if data.len() < 8 + size || data[..8] != RandomnessAccountData::DISCRIMINATOR {
+        bail!("not a Switchboard randomness account");
+    }
+    let randomness: RandomnessAccountData = bytemuck::pod_read_unaligned(&data[8..8 + size]);
+    if randomness.reveal_slot < randomness.seed_slot {
+        bail!("randomness has not been revealed");
+    }
+    Ok(randomness.value)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
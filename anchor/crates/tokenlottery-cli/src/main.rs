@@ -0,0 +1,496 @@
+//! Admin tool for running lotteries from the command line.
+//!
+//! Every command reads the signing keypair and RPC URL from the global
+//! options, derives the accounts it needs through `tokenlottery-client` and
+//! sends one transaction per step.
+
+use anchor_lang::Discriminator;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signature, Signer},
+    transaction::Transaction,
+};
+use switchboard_on_demand::{QueueAccountData, RandomnessAccountData, RandomnessCommit};
+use tokenlottery::{Prize, TicketFreeze, TokenLottery};
+use tokenlottery_client::{instructions, lottery_address, pda, state, PROGRAM_ID};
+
+mod output;
+mod switchboard;
+
+// initialize_lottery and buy_ticket run several Metaplex CPIs
+const INITIALIZE_LOTTERY_COMPUTE_UNITS: u32 = 300_000;
+const BUY_TICKET_COMPUTE_UNITS: u32 = 200_000;
+
+#[derive(Parser)]
+#[command(name = "tokenlottery", about = "Operate tokenlottery lotteries")]
+struct Cli {
+    /// RPC endpoint, a local test validator by default
+    #[arg(long, short = 'u', global = true, env = "TOKENLOTTERY_RPC_URL", default_value = "http://127.0.0.1:8899")]
+    url: String,
+
+    /// Keypair signing and paying for transactions
+    #[arg(long, short = 'k', global = true, env = "TOKENLOTTERY_KEYPAIR", default_value = "~/.config/solana/id.json")]
+    keypair: String,
+
+    #[arg(long, global = true, value_enum, default_value_t = Format::Human)]
+    output: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Human,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Freeze {
+    /// Tickets can always be transferred
+    Transferable,
+    /// Tickets are frozen when minted, only to recipients that sign
+    Soulbound,
+}
+
+impl From<Freeze> for TicketFreeze {
+    fn from(freeze: Freeze) -> Self {
+        match freeze {
+            Freeze::Transferable => TicketFreeze::Transferable,
+            Freeze::Soulbound => TicketFreeze::Soulbound,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a lottery owned by the keypair, initializing the registry if needed
+    Create {
+        /// First slot tickets can be bought in
+        #[arg(long, default_value_t = 0)]
+        start: u64,
+        /// Last slot tickets can be bought in
+        #[arg(long, conflicts_with = "duration", required_unless_present = "duration")]
+        end: Option<u64>,
+        /// Number of slots from now until sales close, instead of `--end`
+        #[arg(long)]
+        duration: Option<u64>,
+        /// Ticket price in lamports
+        #[arg(long)]
+        price: u64,
+        /// Tickets needed for the draw to go ahead
+        #[arg(long, default_value_t = 1)]
+        min_tickets: u64,
+        /// Ticket shards to sell through in parallel
+        #[arg(long, default_value_t = 0)]
+        shards: u8,
+        /// When ticket transfers are frozen
+        #[arg(long, value_enum, default_value_t = Freeze::Transferable)]
+        freeze: Freeze,
+    },
+    /// Print a lottery's state
+    Status { lottery: Pubkey },
+    /// List the lotteries created by an authority
+    List {
+        /// Authority to list, the keypair by default
+        #[arg(long, conflicts_with = "all")]
+        authority: Option<Pubkey>,
+        /// List every lottery of the program instead
+        #[arg(long)]
+        all: bool,
+    },
+    /// Buy the next ticket of a lottery
+    Buy {
+        lottery: Pubkey,
+        /// Highest price to pay, the current price by default
+        #[arg(long)]
+        max_price: Option<u64>,
+        /// Wallet receiving the ticket, the keypair by default. Soulbound
+        /// lotteries only mint to the keypair, which signs as the recipient
+        #[arg(long)]
+        recipient: Option<Pubkey>,
+        #[arg(long)]
+        referrer: Option<Pubkey>,
+        /// Buy through this ticket shard instead of the lottery
+        #[arg(long)]
+        shard: Option<u8>,
+    },
+    /// Commit the Switchboard randomness account and the lottery to it in
+    /// one transaction, the keypair must be the randomness account's authority.
+    /// Replaces the lottery's randomness once it can no longer be revealed
+    Commit {
+        lottery: Pubkey,
+        #[arg(long)]
+        randomness: Pubkey,
+    },
+    /// Add the tickets sold by shards to the lottery, required before the draw
+    Finalize { lottery: Pubkey },
+    /// Reveal the committed randomness and choose the winner in one
+    /// transaction, finalizing shard sales first
+    Draw { lottery: Pubkey },
+    /// Claim the pot and prizes with the winning ticket
+    Claim {
+        lottery: Pubkey,
+        /// Winning ticket mint, derived from the keypair when it bought the ticket
+        #[arg(long)]
+        ticket_mint: Option<Pubkey>,
+        /// Claim on behalf of this holder, paying it or the recipient it designated
+        #[arg(long, requires = "ticket_mint")]
+        holder: Option<Pubkey>,
+    },
+    /// Have prizes claimed for a ticket held by the keypair paid to another wallet
+    Designate {
+        #[arg(long)]
+        ticket_mint: Pubkey,
+        #[arg(long)]
+        recipient: Pubkey,
+    },
+    /// Burn a losing ticket after the draw and reclaim its rent
+    Burn {
+        lottery: Pubkey,
+        #[arg(long)]
+        ticket_mint: Pubkey,
+    },
+    /// Cancel a lottery still on sale, or one that missed its minimum,
+    /// returning deposited prizes to their depositors
+    Cancel { lottery: Pubkey },
+    /// Close a settled lottery and reclaim its rent
+    Close { lottery: Pubkey },
+}
+
+struct App {
+    client: RpcClient,
+    payer: Keypair,
+    format: Format,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let keypair_path = expand_home(&cli.keypair);
+    let payer = read_keypair_file(&keypair_path)
+        .map_err(|err| anyhow::anyhow!("reading keypair {keypair_path}: {err}"))?;
+    let app = App {
+        client: RpcClient::new_with_commitment(cli.url, CommitmentConfig::confirmed()),
+        payer,
+        format: cli.output,
+    };
+
+    match cli.command {
+        Command::Create { start, end, duration, price, min_tickets, shards, freeze } => {
+            let end = match (end, duration) {
+                (Some(end), _) => end,
+                (None, Some(duration)) => app.client.get_slot()? + duration,
+                (None, None) => unreachable!("clap requires --end or --duration"),
+            };
+            create(&app, start, end, price, min_tickets, shards, freeze.into())
+        }
+        Command::Status { lottery } => {
+            let state = fetch_lottery(&app, &lottery)?;
+            output::lottery(app.format, &lottery, &state);
+            Ok(())
+        }
+        Command::List { authority, all } => list(&app, authority, all),
+        Command::Buy { lottery, max_price, recipient, referrer, shard } => {
+            buy(&app, &lottery, max_price, recipient, referrer, shard)
+        }
+        Command::Commit { lottery, randomness } => commit(&app, &lottery, &randomness),
+        Command::Finalize { lottery } => {
+            let state = fetch_lottery(&app, &lottery)?;
+            if !state.is_sharding_pending() {
+                bail!("lottery {lottery} has no shard sales to finalize");
+            }
+            report(&app, "finalize_shards", send(&app, &[instructions::finalize_shards(&state)])?)
+        }
+        Command::Draw { lottery } => draw(&app, &lottery),
+        Command::Claim { lottery, ticket_mint, holder } => claim(&app, &lottery, ticket_mint, holder),
+        Command::Designate { ticket_mint, recipient } => {
+            let holder = app.payer.pubkey();
+            let token_account = pda::associated_token_account(&holder, &ticket_mint);
+            let ix = instructions::designate_prize_recipient(&holder, &token_account, &ticket_mint, &recipient);
+            report(&app, "designate", send(&app, &[ix])?)
+        }
+        Command::Burn { lottery, ticket_mint } => {
+            let state = fetch_lottery(&app, &lottery)?;
+            let ix = instructions::burn_ticket(&app.payer.pubkey(), &state, &ticket_mint);
+            report(&app, "burn", send(&app, &[ix])?)
+        }
+        Command::Cancel { lottery } => {
+            let prizes = fetch_prizes(&app, &lottery)?;
+            let ix = instructions::cancel_lottery(&app.payer.pubkey(), &lottery, &prizes);
+            report(&app, "cancel", send(&app, &[ix])?)
+        }
+        Command::Close { lottery } => {
+            let ix = instructions::close_lottery(&app.payer.pubkey(), &lottery);
+            report(&app, "close", send(&app, &[ix])?)
+        }
+    }
+}
+
+fn create(
+    app: &App,
+    start: u64,
+    end: u64,
+    price: u64,
+    min_tickets: u64,
+    shards: u8,
+    ticket_freeze: TicketFreeze,
+) -> Result<()> {
+    let authority = app.payer.pubkey();
+    let mut setup = Vec::new();
+
+    let registry = pda::registry().0;
+    let id = match app.client.get_account_data(&registry) {
+        Ok(data) => state::decode::<state::Registry>(&data)?.lottery_count,
+        Err(_) => {
+            setup.push(instructions::initialize_registry(&authority));
+            0
+        }
+    };
+    let authority_index = match app.client.get_account_data(&pda::authority_index(&authority).0) {
+        Ok(data) => state::decode::<state::AuthorityIndex>(&data)?.lottery_count,
+        Err(_) => 0,
+    };
+
+    setup.push(instructions::initialize_config(&authority, id, authority_index, start, end, price, min_tickets));
+    let config = send(app, &setup)?;
+    output::signature(app.format, "initialize_config", &config);
+
+    let collection = send_with_budget(app, INITIALIZE_LOTTERY_COMPUTE_UNITS, &[instructions::initialize_lottery(&authority, id)])?;
+    output::signature(app.format, "initialize_lottery", &collection);
+
+    let lottery = pda::token_lottery(&authority, id).0;
+    // shards freeze the configuration, so the freeze policy goes first
+    if ticket_freeze != TicketFreeze::Transferable {
+        let ix = instructions::configure_ticket_freeze(&authority, &lottery, ticket_freeze);
+        report(app, "configure_ticket_freeze", send(app, &[ix])?)?;
+    }
+    if shards > 0 {
+        let setup: Vec<_> =
+            (0..shards).map(|shard| instructions::initialize_ticket_shard(&authority, &lottery, shard)).collect();
+        report(app, "initialize_ticket_shard", send(app, &setup)?)?;
+    }
+
+    let state = fetch_lottery(app, &lottery)?;
+    output::lottery(app.format, &lottery, &state);
+    Ok(())
+}
+
+fn list(app: &App, authority: Option<Pubkey>, all: bool) -> Result<()> {
+    let mut lotteries = Vec::new();
+    if all {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, TokenLottery::DISCRIMINATOR.to_vec()))]),
+            account_config: RpcAccountInfoConfig { encoding: Some(UiAccountEncoding::Base64), ..Default::default() },
+            ..Default::default()
+        };
+        for (address, account) in app.client.get_program_accounts_with_config(&PROGRAM_ID, config)? {
+            lotteries.push((address, state::decode_token_lottery(&account.data)?));
+        }
+        lotteries.sort_by_key(|(_, lottery)| lottery.id);
+    } else {
+        let authority = authority.unwrap_or_else(|| app.payer.pubkey());
+        let count = match app.client.get_account_data(&pda::authority_index(&authority).0) {
+            Ok(data) => state::decode::<state::AuthorityIndex>(&data)?.lottery_count,
+            Err(_) => 0,
+        };
+        for index in 0..count {
+            let entry = app.client.get_account_data(&pda::authority_lottery(&authority, index).0)?;
+            let entry = state::decode::<state::AuthorityLottery>(&entry)?;
+            // closed lotteries keep their index entry
+            if let Ok(lottery) = fetch_lottery(app, &entry.lottery) {
+                lotteries.push((entry.lottery, lottery));
+            }
+        }
+    }
+    output::lotteries(app.format, &lotteries);
+    Ok(())
+}
+
+fn buy(
+    app: &App,
+    lottery: &Pubkey,
+    max_price: Option<u64>,
+    recipient: Option<Pubkey>,
+    referrer: Option<Pubkey>,
+    shard: Option<u8>,
+) -> Result<()> {
+    let state = fetch_lottery(app, lottery)?;
+    let max_price = match max_price {
+        Some(max_price) => max_price,
+        None => state.current_price(app.client.get_slot()?)?,
+    };
+
+    let mut builder = instructions::BuyTicket::new(&app.payer.pubkey(), &state);
+    if let Some(recipient) = recipient {
+        if state.ticket_freeze == TicketFreeze::Soulbound && recipient != app.payer.pubkey() {
+            bail!("lottery {lottery} mints soulbound tickets, which the recipient has to sign for");
+        }
+        builder = builder.recipient(&recipient);
+    }
+    if let Some(referrer) = referrer {
+        builder = builder.referrer(&referrer);
+    }
+    let mut index = state.number_of_tickets;
+    if let Some(shard) = shard {
+        let address = pda::ticket_shard(lottery, shard).0;
+        let data = app
+            .client
+            .get_account_data(&address)
+            .with_context(|| format!("fetching ticket shard {shard} of {lottery}"))?;
+        let ticket_shard = state::decode::<state::TicketShard>(&data)?;
+        index = ticket_shard.next_index();
+        builder = builder.shard(&ticket_shard);
+    }
+
+    let signature = send_with_budget(app, BUY_TICKET_COMPUTE_UNITS, &[builder.instruction(max_price)])?;
+    output::signature(app.format, "buy", &signature);
+    output::ticket(app.format, &builder.ticket_mint(), index);
+    Ok(())
+}
+
+// `commit_a_winner` only accepts randomness seeded in the previous slot, so
+// the Switchboard commit has to land in the same transaction.
+fn commit(app: &App, lottery: &Pubkey, randomness: &Pubkey) -> Result<()> {
+    let account = app
+        .client
+        .get_account(randomness)
+        .with_context(|| format!("fetching randomness account {randomness}"))?;
+    let randomness_data: RandomnessAccountData = switchboard::decode(&account.data)?;
+    if randomness_data.authority != app.payer.pubkey() {
+        bail!("randomness account {randomness} is owned by authority {}", randomness_data.authority);
+    }
+
+    let queue_data = app
+        .client
+        .get_account_data(&randomness_data.queue)
+        .with_context(|| format!("fetching queue {}", randomness_data.queue))?;
+    let queue: QueueAccountData = switchboard::decode(&queue_data)?;
+    let oracles = &queue.oracle_keys[..(queue.oracle_keys_len as usize).min(queue.oracle_keys.len())];
+    let Some(oracle) = oracles.get(app.client.get_slot()? as usize % oracles.len().max(1)) else {
+        bail!("queue {} has no oracles", randomness_data.queue);
+    };
+
+    let mut switchboard_commit =
+        RandomnessCommit::build_ix(*randomness, randomness_data.queue, *oracle, app.payer.pubkey())?;
+    // the randomness account's owner, which `commit_a_winner` checks as well
+    switchboard_commit.program_id = account.owner;
+    let state = fetch_lottery(app, lottery)?;
+    if state.randomness_account == Pubkey::default() {
+        let ix = instructions::commit_a_winner(&app.payer.pubkey(), lottery, randomness);
+        report(app, "commit", send(app, &[switchboard_commit, ix])?)
+    } else {
+        let ix = instructions::recommit_a_winner(&app.payer.pubkey(), &state, randomness);
+        report(app, "recommit", send(app, &[switchboard_commit, ix])?)
+    }
+}
+
+// `choose_a_winner` only reads the randomness in the slot it is revealed, so
+// the Switchboard reveal has to land in the same transaction.
+fn draw(app: &App, lottery: &Pubkey) -> Result<()> {
+    let mut state = fetch_lottery(app, lottery)?;
+    if state.randomness_account == Pubkey::default() {
+        bail!("lottery {lottery} has no committed randomness, run `commit` first");
+    }
+    if state.is_sharding_pending() {
+        report(app, "finalize_shards", send(app, &[instructions::finalize_shards(&state)])?)?;
+        state = fetch_lottery(app, lottery)?;
+    }
+
+    let reveal = switchboard::reveal_instruction(&app.client, &state.randomness_account, &app.payer.pubkey())?;
+    let ix = instructions::choose_a_winner(&app.payer.pubkey(), &state);
+    let signature = send(app, &[reveal, ix])?;
+    let state = fetch_lottery(app, lottery)?;
+    output::signature(app.format, "draw", &signature);
+    output::lottery(app.format, lottery, &state);
+    Ok(())
+}
+
+fn claim(app: &App, lottery: &Pubkey, ticket_mint: Option<Pubkey>, holder: Option<Pubkey>) -> Result<()> {
+    let winner = app.payer.pubkey();
+    let state = fetch_lottery(app, lottery)?;
+    if !state.is_winner_chosen {
+        bail!("lottery {lottery} has not drawn a winner yet");
+    }
+    let ticket_mint = ticket_mint.unwrap_or_else(|| pda::ticket_mint(&winner, state.id, state.winner).0);
+    let prizes = fetch_prizes(app, lottery)?;
+    let Some(holder) = holder else {
+        let ix = instructions::claim_prize(&winner, &state, &ticket_mint, &prizes);
+        return report(app, "claim", send(app, &[ix])?);
+    };
+
+    // the designation only counts while its holder still holds the ticket
+    let recipient = app
+        .client
+        .get_account_data(&pda::prize_recipient(&ticket_mint).0)
+        .ok()
+        .and_then(|data| state::decode::<state::PrizeRecipient>(&data).ok())
+        .filter(|designation| designation.holder == holder)
+        .map(|designation| designation.recipient);
+    let token_account = pda::associated_token_account(&holder, &ticket_mint);
+    let ix = instructions::claim_prize_for(&holder, &token_account, recipient.as_ref(), &state, &ticket_mint, &prizes);
+    report(app, "claim", send(app, &[ix])?)
+}
+
+fn fetch_lottery(app: &App, lottery: &Pubkey) -> Result<TokenLottery> {
+    let data = app
+        .client
+        .get_account_data(lottery)
+        .with_context(|| format!("fetching lottery {lottery}"))?;
+    let state = state::decode_token_lottery(&data)?;
+    if lottery_address(&state) != *lottery {
+        bail!("{lottery} is not a lottery account");
+    }
+    Ok(state)
+}
+
+// Prizes still held for `lottery`, passed along to claim_prize and cancel_lottery.
+fn fetch_prizes(app: &App, lottery: &Pubkey) -> Result<Vec<Prize>> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, Prize::DISCRIMINATOR.to_vec())),
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(8, lottery.to_bytes().to_vec())),
+        ]),
+        account_config: RpcAccountInfoConfig { encoding: Some(UiAccountEncoding::Base64), ..Default::default() },
+        ..Default::default()
+    };
+    app.client
+        .get_program_accounts_with_config(&PROGRAM_ID, config)?
+        .into_iter()
+        .map(|(_, account)| Ok(state::decode::<Prize>(&account.data)?))
+        .collect()
+}
+
+fn send(app: &App, instructions: &[Instruction]) -> Result<Signature> {
+    let blockhash = app.client.get_latest_blockhash()?;
+    let tx = Transaction::new_signed_with_payer(instructions, Some(&app.payer.pubkey()), &[&app.payer], blockhash);
+    Ok(app.client.send_and_confirm_transaction(&tx)?)
+}
+
+fn send_with_budget(app: &App, units: u32, instructions: &[Instruction]) -> Result<Signature> {
+    let mut with_budget = vec![ComputeBudgetInstruction::set_compute_unit_limit(units)];
+    with_budget.extend_from_slice(instructions);
+    send(app, &with_budget)
+}
+
+fn report(app: &App, step: &str, signature: Signature) -> Result<()> {
+    output::signature(app.format, step, &signature);
+    Ok(())
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{home}/{rest}"),
+        _ => path.to_owned(),
+    }
+}
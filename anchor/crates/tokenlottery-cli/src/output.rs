@@ -0,0 +1,144 @@
+//! Printing of command results, as `key: value` lines or one JSON object
+//! per line.
+
+use serde_json::{json, Map, Value};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use tokenlottery::{BondingCurve, PurchaseGate, TicketFreeze, TokenLottery};
+
+use crate::Format;
+
+pub fn lottery(format: Format, address: &Pubkey, lottery: &TokenLottery) {
+    print(format, lottery_json(address, lottery));
+}
+
+pub fn lotteries(format: Format, lotteries: &[(Pubkey, TokenLottery)]) {
+    match format {
+        Format::Json => {
+            let list = lotteries.iter().map(|(address, lottery)| lottery_json(address, lottery)).collect();
+            println!("{}", Value::Array(list));
+        }
+        Format::Human => {
+            println!("{:>6}  {:<44}  {:>8}  {:>14}  state", "id", "address", "tickets", "pot");
+            for (address, lottery) in lotteries {
+                println!(
+                    "{:>6}  {:<44}  {:>8}  {:>14}  {}",
+                    lottery.id,
+                    address,
+                    lottery.number_of_tickets,
+                    lottery.lottery_pot_amount,
+                    phase(lottery)
+                );
+            }
+        }
+    }
+}
+
+pub fn signature(format: Format, step: &str, signature: &Signature) {
+    print(format, json!({ "step": step, "signature": signature.to_string() }));
+}
+
+pub fn ticket(format: Format, mint: &Pubkey, index: u64) {
+    print(format, json!({ "ticket_mint": mint.to_string(), "ticket_index": index }));
+}
+
+fn print(format: Format, value: Value) {
+    match format {
+        Format::Json => println!("{value}"),
+        Format::Human => {
+            let Value::Object(fields) = value else { return };
+            let width = fields.keys().map(String::len).max().unwrap_or(0);
+            for (key, value) in fields {
+                match value {
+                    Value::String(text) => println!("{key:>width$}: {text}"),
+                    other => println!("{key:>width$}: {other}"),
+                }
+            }
+        }
+    }
+}
+
+fn phase(lottery: &TokenLottery) -> &'static str {
+    if lottery.is_cancelled {
+        "cancelled"
+    } else if lottery.is_prize_claimed {
+        "claimed"
+    } else if lottery.is_winner_chosen {
+        "drawn"
+    } else if lottery.randomness_account != Pubkey::default() {
+        "committed"
+    } else {
+        "selling"
+    }
+}
+
+fn lottery_json(address: &Pubkey, lottery: &TokenLottery) -> Value {
+    let mut fields = Map::new();
+    let mut field = |key: &str, value: Value| {
+        fields.insert(key.to_owned(), value);
+    };
+    field("address", json!(address.to_string()));
+    field("version", json!(lottery.version));
+    field("id", json!(lottery.id));
+    field("authority", json!(lottery.authority.to_string()));
+    field("phase", json!(phase(lottery)));
+    field("lottery_start", json!(lottery.lottery_start));
+    field("lottery_end", json!(lottery.lottery_end));
+    field("price", json!(lottery.price));
+    field(
+        "price_tiers",
+        json!(lottery
+            .price_tiers
+            .iter()
+            .map(|tier| json!({ "until": tier.until, "price": tier.price }))
+            .collect::<Vec<_>>()),
+    );
+    field(
+        "bonding_curve",
+        match lottery.bonding_curve {
+            BondingCurve::Flat => json!("flat"),
+            BondingCurve::Linear { step } => json!({ "linear": { "step": step } }),
+            BondingCurve::Exponential { growth_bps } => json!({ "exponential": { "growth_bps": growth_bps } }),
+        },
+    );
+    field("number_of_tickets", json!(lottery.number_of_tickets));
+    field("min_tickets", json!(lottery.min_tickets));
+    field("promo_tickets", json!(lottery.promo_tickets));
+    field("max_promo_tickets", json!(lottery.max_promo_tickets));
+    field("lottery_pot_amount", json!(lottery.lottery_pot_amount));
+    field("sponsor_amount", json!(lottery.sponsor_amount));
+    field("principal_amount", json!(lottery.principal_amount));
+    field("prize_count", json!(lottery.prize_count));
+    field("referral_bps", json!(lottery.referral_bps));
+    field("referral_total", json!(lottery.referral_total));
+    field(
+        "purchase_gate",
+        match lottery.purchase_gate {
+            PurchaseGate::None => json!("none"),
+            PurchaseGate::TokenBalance { mint, min_amount } => {
+                json!({ "token_balance": { "mint": mint.to_string(), "min_amount": min_amount } })
+            }
+            PurchaseGate::Collection { collection_mint } => {
+                json!({ "collection": { "collection_mint": collection_mint.to_string() } })
+            }
+        },
+    );
+    field("presale_start", json!(lottery.presale_start));
+    field("presale_end", json!(lottery.presale_end));
+    field("is_weighted", json!(lottery.is_weighted));
+    field("is_no_loss", json!(lottery.is_no_loss));
+    field("shard_count", json!(lottery.shard_count));
+    field("is_shards_finalized", json!(lottery.is_shards_finalized));
+    field(
+        "ticket_freeze",
+        json!(match lottery.ticket_freeze {
+            TicketFreeze::Transferable => "transferable",
+            TicketFreeze::Soulbound => "soulbound",
+        }),
+    );
+    field("is_cancelled", json!(lottery.is_cancelled));
+    field("randomness_account", json!(lottery.randomness_account.to_string()));
+    field("is_winner_chosen", json!(lottery.is_winner_chosen));
+    field("winner", json!(lottery.winner));
+    field("is_prize_claimed", json!(lottery.is_prize_claimed));
+    Value::Object(fields)
+}
@@ -0,0 +1,188 @@
+//! Switchboard On-Demand randomness: decoding its accounts and building the
+//! reveal instruction `choose_a_winner` has to share a transaction with.
+//!
+//! The Rust crate has no reveal builder, so this follows the TypeScript SDK:
+//! the oracle assigned at commit signs the value through its gateway, and the
+//! instruction's accounts are laid out after the IDL Switchboard publishes on
+//! chain.
+
+use std::io::Read;
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::read::ZlibDecoder;
+use serde_json::{json, Value};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+use switchboard_on_demand::{Discriminator, OracleAccountData, RandomnessAccountData};
+use tokenlottery_client::{pda, TOKEN_PROGRAM_ID};
+
+// mint of wrapped SOL, the reveal pays the oracle's reward from an escrow in it
+const NATIVE_MINT: Pubkey = solana_sdk::pubkey!("So11111111111111111111111111111111111111112");
+
+// Reads a Switchboard account past its 8 byte discriminator.
+pub fn decode<T: bytemuck::Pod + Discriminator>(data: &[u8]) -> Result<T> {
+    let size = std::mem::size_of::<T>();
+    if data.len() < 8 + size || data[..8] != T::DISCRIMINATOR {
+        bail!("not a Switchboard {} account", std::any::type_name::<T>().rsplit("::").next().unwrap_or_default());
+    }
+    Ok(bytemuck::pod_read_unaligned(&data[8..8 + size]))
+}
+
+/// Builds the `randomness_reveal` instruction for a committed randomness
+/// account. `payer` has to be its authority and pays the oracle's reward.
+pub fn reveal_instruction(client: &RpcClient, randomness: &Pubkey, payer: &Pubkey) -> Result<Instruction> {
+    let account = client
+        .get_account(randomness)
+        .with_context(|| format!("fetching randomness account {randomness}"))?;
+    let switchboard = account.owner;
+    let data: RandomnessAccountData = decode(&account.data)?;
+    if data.oracle == Pubkey::default() {
+        bail!("randomness account {randomness} has not been committed");
+    }
+    // the randomness authority signs the reveal as well
+    if data.authority != *payer {
+        bail!("randomness account {randomness} is owned by authority {}", data.authority);
+    }
+
+    let oracle_data = client
+        .get_account_data(&data.oracle)
+        .with_context(|| format!("fetching oracle {}", data.oracle))?;
+    let oracle: OracleAccountData = decode(&oracle_data)?;
+    let Some(gateway) = oracle.gateway_uri() else {
+        bail!("oracle {} has no gateway", data.oracle);
+    };
+    let response = fetch_reveal(&gateway, randomness, &data, &client.url())?;
+
+    let (discriminator, accounts) = reveal_layout(client, &switchboard)?;
+    let accounts = accounts
+        .into_iter()
+        .map(|account| {
+            let pubkey = match account.name.as_str() {
+                "randomness" => *randomness,
+                "oracle" => data.oracle,
+                "queue" => data.queue,
+                "stats" => Pubkey::find_program_address(&[b"OracleRandomnessStats", data.oracle.as_ref()], &switchboard).0,
+                "authority" => data.authority,
+                "payer" => *payer,
+                "recentslothashes" => sysvar::slot_hashes::ID,
+                "systemprogram" => system_program::ID,
+                "rewardescrow" => pda::associated_token_account(randomness, &NATIVE_MINT),
+                "tokenprogram" => TOKEN_PROGRAM_ID,
+                "associatedtokenprogram" => tokenlottery_client::ASSOCIATED_TOKEN_PROGRAM_ID,
+                "wrappedsolmint" => NATIVE_MINT,
+                "programstate" => Pubkey::find_program_address(&[b"STATE"], &switchboard).0,
+                other => bail!("unknown account {other} in Switchboard's randomness_reveal"),
+            };
+            Ok(AccountMeta { pubkey, is_signer: account.is_signer, is_writable: account.is_writable })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // RandomnessRevealParams { signature: [u8; 64], recovery_id: u8, value: [u8; 32] }
+    let mut params = discriminator.to_vec();
+    params.extend_from_slice(&response.signature);
+    params.push(response.recovery_id);
+    params.extend_from_slice(&response.value);
+
+    Ok(Instruction { program_id: switchboard, accounts, data: params })
+}
+
+// Account of `randomness_reveal` as the IDL lists it, the name lowercased
+// without underscores.
+struct RevealAccount {
+    name: String,
+    is_writable: bool,
+    is_signer: bool,
+}
+
+struct RevealResponse {
+    signature: [u8; 64],
+    recovery_id: u8,
+    value: [u8; 32],
+}
+
+// Asks the oracle's gateway to sign the value of the committed slot hash.
+fn fetch_reveal(gateway: &str, randomness: &Pubkey, data: &RandomnessAccountData, rpc: &str) -> Result<RevealResponse> {
+    let url = format!("{}/gateway/api/v1/randomness_reveal", gateway.trim_end_matches('/'));
+    let randomness_key: String = randomness.to_bytes().iter().map(|byte| format!("{byte:02x}")).collect();
+    let body = json!({
+        "slothash": data.seed_slothash.to_vec(),
+        "randomness_key": randomness_key,
+        "slot": data.seed_slot,
+        "rpc": rpc,
+    });
+    let response: Value = reqwest::blocking::Client::new()
+        .post(&url)
+        .json(&body)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("requesting the reveal from {url}"))?
+        .json()
+        .with_context(|| format!("reading the reveal from {url}"))?;
+
+    let signature = response["signature"].as_str().map(|signature| STANDARD.decode(signature));
+    let recovery_id = response["recovery_id"].as_u64().and_then(|id| u8::try_from(id).ok());
+    let value: Option<Vec<u8>> = response["value"]
+        .as_array()
+        .and_then(|value| value.iter().map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok())).collect());
+    match (signature, recovery_id, value) {
+        (Some(Ok(signature)), Some(recovery_id), Some(value)) => Ok(RevealResponse {
+            signature: signature.try_into().map_err(|_| anyhow::anyhow!("reveal signature is not 64 bytes"))?,
+            recovery_id,
+            value: value.try_into().map_err(|_| anyhow::anyhow!("revealed value is not 32 bytes"))?,
+        }),
+        _ => bail!("unexpected reveal from {url}: {response}"),
+    }
+}
+
+// Discriminator of `randomness_reveal` and its accounts in order. Reads both
+// the legacy (`isMut`, `isSigner`) and current (`writable`, `signer`,
+// `discriminator`) IDL formats.
+fn reveal_layout(client: &RpcClient, switchboard: &Pubkey) -> Result<([u8; 8], Vec<RevealAccount>)> {
+    let base = Pubkey::find_program_address(&[], switchboard).0;
+    let address = Pubkey::create_with_seed(&base, "anchor:idl", switchboard)?;
+    let data = client
+        .get_account_data(&address)
+        .with_context(|| format!("fetching the IDL of Switchboard program {switchboard}"))?;
+    // discriminator, authority, then the length prefixed, zlib compressed JSON
+    let Some(len) = data.get(40..44).map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize) else {
+        bail!("IDL account {address} is too short");
+    };
+    let Some(compressed) = data.get(44..44 + len) else {
+        bail!("IDL account {address} is too short");
+    };
+    let mut json = String::new();
+    ZlibDecoder::new(compressed).read_to_string(&mut json).context("inflating the Switchboard IDL")?;
+    let idl: Value = serde_json::from_str(&json).context("parsing the Switchboard IDL")?;
+
+    let normalize = |name: &str| name.replace('_', "").to_lowercase();
+    let Some(reveal) = idl["instructions"]
+        .as_array()
+        .and_then(|instructions| instructions.iter().find(|ix| ix["name"].as_str().map(normalize).as_deref() == Some("randomnessreveal")))
+    else {
+        bail!("Switchboard IDL has no randomness_reveal instruction");
+    };
+    let discriminator = reveal["discriminator"]
+        .as_array()
+        .and_then(|bytes| bytes.iter().map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok())).collect::<Option<Vec<_>>>())
+        .and_then(|bytes| <[u8; 8]>::try_from(bytes).ok())
+        .unwrap_or_else(|| hash(b"global:randomness_reveal").to_bytes()[..8].try_into().unwrap());
+
+    let flag = |account: &Value, legacy: &str, current: &str| account[legacy].as_bool().or(account[current].as_bool()).unwrap_or(false);
+    let accounts = reveal["accounts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|account| RevealAccount {
+            name: normalize(account["name"].as_str().unwrap_or_default()),
+            is_writable: flag(account, "isMut", "writable"),
+            is_signer: flag(account, "isSigner", "signer"),
+        })
+        .collect();
+    Ok((discriminator, accounts))
+}
@@ -0,0 +1,743 @@
+//! Instruction builders, one per program instruction.
+//!
+//! Builders resolve every PDA themselves. Instructions that act on an
+//! existing lottery take either its address, or the decoded `TokenLottery`
+//! when accounts are derived from its authority, id or ticket count.
+
+use anchor_lang::{
+    prelude::{AccountMeta, Pubkey},
+    solana_program::{instruction::Instruction, sysvar},
+    system_program, InstructionData, ToAccountMetas,
+};
+use tokenlottery::{
+    accounts, instruction, BondingCurve, PriceTier, Prize, PurchaseGate, TicketFreeze, TicketShard, TokenLottery,
+};
+
+use crate::{lottery_address, pda, ASSOCIATED_TOKEN_PROGRAM_ID, METADATA_PROGRAM_ID, TOKEN_PROGRAM_ID};
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: tokenlottery::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Remaining accounts that `claim_prize` and `cancel_lottery` use to release
+/// deposited prizes, paid to `recipient` or back to each depositor when
+/// `None`.
+pub fn prize_accounts(prizes: &[Prize], recipient: Option<&Pubkey>) -> Vec<AccountMeta> {
+    prizes
+        .iter()
+        .flat_map(|prize| {
+            let owner = recipient.unwrap_or(&prize.depositor);
+            [
+                AccountMeta::new(pda::prize(&prize.lottery, &prize.mint).0, false),
+                AccountMeta::new(prize.vault, false),
+                AccountMeta::new_readonly(prize.mint, false),
+                AccountMeta::new(pda::associated_token_account(owner, &prize.mint), false),
+                AccountMeta::new(prize.depositor, false),
+            ]
+        })
+        .collect()
+}
+
+pub fn initialize_registry(payer: &Pubkey) -> Instruction {
+    build(
+        accounts::InitializeRegistry {
+            payer: *payer,
+            registry: pda::registry().0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeRegistry {},
+    )
+}
+
+/// Creates lottery `id`, which must be the registry's current
+/// `lottery_count`. `authority_index` is the authority's current
+/// `AuthorityIndex::lottery_count`, or 0 for its first lottery.
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_config(
+    authority: &Pubkey,
+    id: u64,
+    authority_index: u64,
+    start: u64,
+    end: u64,
+    price: u64,
+    min_tickets: u64,
+) -> Instruction {
+    build(
+        accounts::InitializeConfig {
+            payer: *authority,
+            registry: pda::registry().0,
+            authority_index: pda::authority_index(authority).0,
+            authority_lottery: pda::authority_lottery(authority, authority_index).0,
+            token_lottery: pda::token_lottery(authority, id).0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeConfig { start, end, price, min_tickets },
+    )
+}
+
+pub fn migrate_lottery(payer: &Pubkey, token_lottery: &Pubkey) -> Instruction {
+    build(
+        accounts::MigrateLottery {
+            payer: *payer,
+            token_lottery: *token_lottery,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateLottery {},
+    )
+}
+
+/// Creates the receipt of a ticket sold before the lottery was migrated.
+pub fn backfill_ticket(payer: &Pubkey, lottery: &TokenLottery, ticket_mint: &Pubkey) -> Instruction {
+    let token_lottery = lottery_address(lottery);
+    build(
+        accounts::BackfillTicket {
+            payer: *payer,
+            token_lottery,
+            ticket_mint: *ticket_mint,
+            ticket: pda::ticket(ticket_mint).0,
+            metadata: pda::metadata(ticket_mint).0,
+            collection_mint: pda::collection_mint(&lottery.authority, lottery.id).0,
+            system_program: system_program::ID,
+            token_metadata_program: METADATA_PROGRAM_ID,
+        },
+        instruction::BackfillTicket {},
+    )
+}
+
+pub fn initialize_lottery(authority: &Pubkey, id: u64) -> Instruction {
+    let collection_mint = pda::collection_mint(authority, id).0;
+    build(
+        accounts::InitializeLottery {
+            payer: *authority,
+            collection_mint,
+            metadata: pda::metadata(&collection_mint).0,
+            master_edition: pda::master_edition(&collection_mint).0,
+            collection_token_account: pda::collection_token_account(authority, id).0,
+            token_program: TOKEN_PROGRAM_ID,
+            system_program: system_program::ID,
+            token_metadata_program: METADATA_PROGRAM_ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::InitializeLottery { id },
+    )
+}
+
+pub fn configure_pricing(
+    authority: &Pubkey,
+    token_lottery: &Pubkey,
+    price_tiers: Vec<PriceTier>,
+    bonding_curve: BondingCurve,
+) -> Instruction {
+    build(
+        accounts::ConfigurePricing { payer: *authority, token_lottery: *token_lottery },
+        instruction::ConfigurePricing { price_tiers, bonding_curve },
+    )
+}
+
+/// Builder for `buy_ticket` and `buy_ticket_allowlisted`.
+///
+/// By default the buyer pays rent and receives the ticket. For
+/// `PurchaseGate::TokenBalance` lotteries the buyer's associated token
+/// account of the gate mint is passed as proof, `Collection` gates need
+/// `gate_accounts`.
+pub struct BuyTicket {
+    payer: Pubkey,
+    buyer: Pubkey,
+    recipient: Pubkey,
+    token_lottery: Pubkey,
+    authority: Pubkey,
+    id: u64,
+    ticket_mint: Pubkey,
+    gate_token_account: Option<Pubkey>,
+    gate_metadata: Option<Pubkey>,
+    referral: Option<Pubkey>,
+    entry_weights: Option<Pubkey>,
+    ticket_shard: Option<Pubkey>,
+    recipient_signs: bool,
+}
+
+impl BuyTicket {
+    pub fn new(buyer: &Pubkey, lottery: &TokenLottery) -> Self {
+        let token_lottery = lottery_address(lottery);
+        let gate_token_account = match lottery.purchase_gate {
+            PurchaseGate::TokenBalance { mint, .. } => Some(pda::associated_token_account(buyer, &mint)),
+            _ => None,
+        };
+        Self {
+            payer: *buyer,
+            buyer: *buyer,
+            recipient: *buyer,
+            token_lottery,
+            authority: lottery.authority,
+            id: lottery.id,
+            ticket_mint: pda::ticket_mint(buyer, lottery.id, lottery.number_of_tickets).0,
+            gate_token_account,
+            gate_metadata: None,
+            referral: None,
+            entry_weights: lottery.is_weighted.then(|| pda::entry_weights(&token_lottery).0),
+            ticket_shard: None,
+            recipient_signs: lottery.ticket_freeze == TicketFreeze::Soulbound,
+        }
+    }
+
+    /// Account paying rent for the ticket accounts, e.g. a relayer.
+    pub fn payer(mut self, payer: &Pubkey) -> Self {
+        self.payer = *payer;
+        self
+    }
+
+    /// Wallet receiving the ticket.
+    pub fn recipient(mut self, recipient: &Pubkey) -> Self {
+        self.recipient = *recipient;
+        self
+    }
+
+    /// Has the recipient sign as well. Always set for `TicketFreeze::Soulbound`
+    /// lotteries, which only mint tickets to a recipient that signs.
+    pub fn recipient_signer(mut self) -> Self {
+        self.recipient_signs = true;
+        self
+    }
+
+    pub fn referrer(mut self, referrer: &Pubkey) -> Self {
+        self.referral = Some(pda::referral(&self.token_lottery, referrer).0);
+        self
+    }
+
+    pub fn gate_accounts(mut self, token_account: &Pubkey, metadata: Option<&Pubkey>) -> Self {
+        self.gate_token_account = Some(*token_account);
+        self.gate_metadata = metadata.copied();
+        self
+    }
+
+    /// Buys the next ticket of `shard` through `buy_ticket_sharded`, which
+    /// doesn't write lock the lottery. Allowlisted purchases can't use shards.
+    pub fn shard(mut self, shard: &TicketShard) -> Self {
+        self.ticket_shard = Some(pda::ticket_shard(&self.token_lottery, shard.shard).0);
+        self.ticket_mint = pda::ticket_mint(&self.buyer, self.id, shard.next_index()).0;
+        self
+    }
+
+    /// Mint of the ticket this purchase creates.
+    pub fn ticket_mint(&self) -> Pubkey {
+        self.ticket_mint
+    }
+
+    fn accounts(&self) -> accounts::BuyTicket {
+        let collection_mint = pda::collection_mint(&self.authority, self.id).0;
+        accounts::BuyTicket {
+            payer: self.payer,
+            buyer: self.buyer,
+            recipient: self.recipient,
+            token_lottery: self.token_lottery,
+            ticket: pda::ticket(&self.ticket_mint).0,
+            ticket_mint: self.ticket_mint,
+            destination_token_account: pda::associated_token_account(&self.recipient, &self.ticket_mint),
+            metadata: pda::metadata(&self.ticket_mint).0,
+            master_edition: pda::master_edition(&self.ticket_mint).0,
+            collection_metadata: pda::metadata(&collection_mint).0,
+            collection_master_edition: pda::master_edition(&collection_mint).0,
+            collection_mint,
+            gate_token_account: self.gate_token_account,
+            gate_metadata: self.gate_metadata,
+            referral: self.referral,
+            entry_weights: self.entry_weights,
+            token_program: TOKEN_PROGRAM_ID,
+            system_program: system_program::ID,
+            token_metadata_program: METADATA_PROGRAM_ID,
+            associated_token_program: ASSOCIATED_TOKEN_PROGRAM_ID,
+            rent: sysvar::rent::ID,
+        }
+    }
+
+    // Frozen tickets are delegated to the collection mint, which the
+    // recipient has to sign for.
+    fn recipient_signs(&self, mut ix: Instruction) -> Instruction {
+        if self.recipient_signs {
+            for meta in ix.accounts.iter_mut().filter(|meta| meta.pubkey == self.recipient) {
+                meta.is_signer = true;
+            }
+        }
+        ix
+    }
+
+    pub fn instruction(&self, max_price: u64) -> Instruction {
+        let Some(ticket_shard) = self.ticket_shard else {
+            return self.recipient_signs(build(self.accounts(), instruction::BuyTicket { max_price }));
+        };
+        let accounts = self.accounts();
+        self.recipient_signs(build(
+            accounts::BuyTicketSharded {
+                payer: accounts.payer,
+                buyer: accounts.buyer,
+                recipient: accounts.recipient,
+                token_lottery: accounts.token_lottery,
+                ticket_shard,
+                ticket: accounts.ticket,
+                ticket_mint: accounts.ticket_mint,
+                destination_token_account: accounts.destination_token_account,
+                metadata: accounts.metadata,
+                master_edition: accounts.master_edition,
+                collection_metadata: accounts.collection_metadata,
+                collection_master_edition: accounts.collection_master_edition,
+                collection_mint: accounts.collection_mint,
+                gate_token_account: accounts.gate_token_account,
+                gate_metadata: accounts.gate_metadata,
+                referral: accounts.referral,
+                token_program: accounts.token_program,
+                system_program: accounts.system_program,
+                token_metadata_program: accounts.token_metadata_program,
+                associated_token_program: accounts.associated_token_program,
+                rent: accounts.rent,
+            },
+            instruction::BuyTicketSharded { max_price },
+        ))
+    }
+
+    pub fn allowlisted(&self, max_price: u64, max_allowance: u64, proof: Vec<[u8; 32]>) -> Instruction {
+        self.recipient_signs(build(
+            accounts::BuyTicketAllowlisted {
+                buy: self.accounts(),
+                allowlist_usage: pda::allowlist_usage(&self.token_lottery, &self.buyer).0,
+                system_program: system_program::ID,
+            },
+            instruction::BuyTicketAllowlisted { max_price, max_allowance, proof },
+        ))
+    }
+}
+
+/// Mints a promotional ticket to `recipient`, signed by the lottery
+/// authority. Returns the instruction and the new ticket mint.
+pub fn issue_ticket(recipient: &Pubkey, lottery: &TokenLottery) -> (Instruction, Pubkey) {
+    let authority = lottery.authority;
+    let token_lottery = lottery_address(lottery);
+    let ticket_mint = pda::ticket_mint(&authority, lottery.id, lottery.number_of_tickets).0;
+    let collection_mint = pda::collection_mint(&authority, lottery.id).0;
+    let mut ix = build(
+        accounts::IssueTicket {
+            payer: authority,
+            recipient: *recipient,
+            token_lottery,
+            ticket: pda::ticket(&ticket_mint).0,
+            ticket_mint,
+            destination_token_account: pda::associated_token_account(recipient, &ticket_mint),
+            metadata: pda::metadata(&ticket_mint).0,
+            master_edition: pda::master_edition(&ticket_mint).0,
+            collection_metadata: pda::metadata(&collection_mint).0,
+            collection_master_edition: pda::master_edition(&collection_mint).0,
+            collection_mint,
+            entry_weights: lottery.is_weighted.then(|| pda::entry_weights(&token_lottery).0),
+            token_program: TOKEN_PROGRAM_ID,
+            system_program: system_program::ID,
+            token_metadata_program: METADATA_PROGRAM_ID,
+            associated_token_program: ASSOCIATED_TOKEN_PROGRAM_ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::IssueTicket {},
+    );
+    // soulbound tickets are only minted to a recipient that signs
+    if lottery.ticket_freeze == TicketFreeze::Soulbound {
+        ix.accounts.iter_mut().filter(|meta| meta.pubkey == *recipient).for_each(|meta| meta.is_signer = true);
+    }
+    (ix, ticket_mint)
+}
+
+pub fn configure_promo(authority: &Pubkey, token_lottery: &Pubkey, max_promo_tickets: Option<u64>) -> Instruction {
+    build(
+        accounts::ConfigurePromo { payer: *authority, token_lottery: *token_lottery },
+        instruction::ConfigurePromo { max_promo_tickets },
+    )
+}
+
+pub fn initialize_entry_weights(authority: &Pubkey, token_lottery: &Pubkey) -> Instruction {
+    build(
+        accounts::InitializeEntryWeights {
+            payer: *authority,
+            token_lottery: *token_lottery,
+            entry_weights: pda::entry_weights(token_lottery).0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeEntryWeights {},
+    )
+}
+
+pub fn set_entry_weight(authority: &Pubkey, token_lottery: &Pubkey, index: u64, weight: u64) -> Instruction {
+    build(
+        accounts::SetEntryWeight {
+            payer: *authority,
+            token_lottery: *token_lottery,
+            entry_weights: pda::entry_weights(token_lottery).0,
+        },
+        instruction::SetEntryWeight { index, weight },
+    )
+}
+
+pub fn configure_gate(authority: &Pubkey, token_lottery: &Pubkey, purchase_gate: PurchaseGate) -> Instruction {
+    build(
+        accounts::ConfigureGate { payer: *authority, token_lottery: *token_lottery },
+        instruction::ConfigureGate { purchase_gate },
+    )
+}
+
+pub fn configure_ticket_freeze(authority: &Pubkey, token_lottery: &Pubkey, ticket_freeze: TicketFreeze) -> Instruction {
+    build(
+        accounts::ConfigureTicketFreeze { payer: *authority, token_lottery: *token_lottery },
+        instruction::ConfigureTicketFreeze { ticket_freeze },
+    )
+}
+
+pub fn configure_referrals(authority: &Pubkey, token_lottery: &Pubkey, referral_bps: u16) -> Instruction {
+    build(
+        accounts::ConfigureReferrals { payer: *authority, token_lottery: *token_lottery },
+        instruction::ConfigureReferrals { referral_bps },
+    )
+}
+
+pub fn register_referrer(referrer: &Pubkey, token_lottery: &Pubkey) -> Instruction {
+    build(
+        accounts::RegisterReferrer {
+            referrer: *referrer,
+            token_lottery: *token_lottery,
+            referral: pda::referral(token_lottery, referrer).0,
+            system_program: system_program::ID,
+        },
+        instruction::RegisterReferrer {},
+    )
+}
+
+pub fn claim_referral_rewards(referrer: &Pubkey, token_lottery: &Pubkey) -> Instruction {
+    build(
+        accounts::ClaimReferralRewards {
+            referrer: *referrer,
+            token_lottery: *token_lottery,
+            referral: pda::referral(token_lottery, referrer).0,
+        },
+        instruction::ClaimReferralRewards {},
+    )
+}
+
+pub fn configure_allowlist(
+    authority: &Pubkey,
+    token_lottery: &Pubkey,
+    allowlist_root: [u8; 32],
+    presale_start: u64,
+    presale_end: u64,
+) -> Instruction {
+    build(
+        accounts::ConfigureAllowlist { payer: *authority, token_lottery: *token_lottery },
+        instruction::ConfigureAllowlist { allowlist_root, presale_start, presale_end },
+    )
+}
+
+/// Commits to the Switchboard randomness account `randomness`, which must
+/// be committed in the same transaction.
+pub fn commit_a_winner(authority: &Pubkey, token_lottery: &Pubkey, randomness: &Pubkey) -> Instruction {
+    build(
+        accounts::CommitWinner {
+            payer: *authority,
+            token_lottery: *token_lottery,
+            randomness_account_data: *randomness,
+            system_program: system_program::ID,
+        },
+        instruction::CommitAWinner {},
+    )
+}
+
+/// Commits to the Switchboard randomness account `randomness` in place of
+/// the lottery's, once that one can no longer be revealed. Like
+/// `commit_a_winner`, `randomness` must be committed in the same transaction.
+pub fn recommit_a_winner(authority: &Pubkey, lottery: &TokenLottery, randomness: &Pubkey) -> Instruction {
+    build(
+        accounts::RecommitWinner {
+            payer: *authority,
+            token_lottery: lottery_address(lottery),
+            committed_randomness_account_data: lottery.randomness_account,
+            randomness_account_data: *randomness,
+        },
+        instruction::RecommitAWinner {},
+    )
+}
+
+/// Draws the winner from the randomness account committed to in
+/// `commit_a_winner`.
+pub fn choose_a_winner(authority: &Pubkey, lottery: &TokenLottery) -> Instruction {
+    let token_lottery = lottery_address(lottery);
+    build(
+        accounts::ChooseWinner {
+            payer: *authority,
+            token_lottery,
+            randomness_account_data: lottery.randomness_account,
+            entry_weights: lottery.is_weighted.then(|| pda::entry_weights(&token_lottery).0),
+            draw_result: pda::draw_result(&token_lottery).0,
+            system_program: system_program::ID,
+        },
+        instruction::ChooseAWinner {},
+    )
+}
+
+pub fn close_lottery(authority: &Pubkey, token_lottery: &Pubkey) -> Instruction {
+    build(
+        accounts::CloseLottery { payer: *authority, token_lottery: *token_lottery },
+        instruction::CloseLottery {},
+    )
+}
+
+/// Claims the pot and every prize in `prizes` for the holder of the winning
+/// `ticket_mint`.
+pub fn claim_prize(winner: &Pubkey, lottery: &TokenLottery, ticket_mint: &Pubkey, prizes: &[Prize]) -> Instruction {
+    let collection_mint = pda::collection_mint(&lottery.authority, lottery.id).0;
+    let mut ix = build(
+        accounts::ClaimPrize {
+            payer: *winner,
+            token_lottery: lottery_address(lottery),
+            ticket_mint: *ticket_mint,
+            ticket: pda::ticket(ticket_mint).0,
+            destination_token_account: pda::associated_token_account(winner, ticket_mint),
+            master_edition: pda::master_edition(ticket_mint).0,
+            metadata: pda::metadata(ticket_mint).0,
+            collection_metadata: pda::metadata(&collection_mint).0,
+            collection_mint,
+            token_program: TOKEN_PROGRAM_ID,
+            system_program: system_program::ID,
+            token_metadata_program: METADATA_PROGRAM_ID,
+        },
+        instruction::ClaimPrize {},
+    );
+    ix.accounts.extend(prize_accounts(prizes, Some(winner)));
+    ix
+}
+
+/// Claims the pot and every prize in `prizes` for `holder`, whose
+/// `holder_token_account` holds the winning `ticket_mint`, without its
+/// signature. `recipient` is the wallet the holder designated, if any.
+pub fn claim_prize_for(
+    holder: &Pubkey,
+    holder_token_account: &Pubkey,
+    recipient: Option<&Pubkey>,
+    lottery: &TokenLottery,
+    ticket_mint: &Pubkey,
+    prizes: &[Prize],
+) -> Instruction {
+    let recipient = recipient.unwrap_or(holder);
+    let mut ix = build(
+        accounts::ClaimPrizeFor {
+            holder: *holder,
+            recipient: *recipient,
+            token_lottery: lottery_address(lottery),
+            ticket_mint: *ticket_mint,
+            ticket: pda::ticket(ticket_mint).0,
+            holder_token_account: *holder_token_account,
+            master_edition: pda::master_edition(ticket_mint).0,
+            metadata: pda::metadata(ticket_mint).0,
+            collection_mint: pda::collection_mint(&lottery.authority, lottery.id).0,
+            prize_recipient: pda::prize_recipient(ticket_mint).0,
+            token_program: TOKEN_PROGRAM_ID,
+            token_metadata_program: METADATA_PROGRAM_ID,
+        },
+        instruction::ClaimPrizeFor {},
+    );
+    ix.accounts.extend(prize_accounts(prizes, Some(recipient)));
+    ix
+}
+
+/// Has `claim_prize_for` pay the prize of `ticket_mint` to `recipient`
+/// while `holder` holds the ticket.
+pub fn designate_prize_recipient(
+    holder: &Pubkey,
+    holder_token_account: &Pubkey,
+    ticket_mint: &Pubkey,
+    recipient: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::DesignatePrizeRecipient {
+            holder: *holder,
+            ticket_mint: *ticket_mint,
+            holder_token_account: *holder_token_account,
+            prize_recipient: pda::prize_recipient(ticket_mint).0,
+            system_program: system_program::ID,
+        },
+        instruction::DesignatePrizeRecipient { recipient: *recipient },
+    )
+}
+
+pub fn fund_pot(sponsor: &Pubkey, token_lottery: &Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::FundPot {
+            sponsor: *sponsor,
+            token_lottery: *token_lottery,
+            sponsorship: pda::sponsorship(token_lottery, sponsor).0,
+            system_program: system_program::ID,
+        },
+        instruction::FundPot { amount },
+    )
+}
+
+/// Cancels the lottery and returns every prize in `prizes` to its depositor.
+pub fn cancel_lottery(authority: &Pubkey, token_lottery: &Pubkey, prizes: &[Prize]) -> Instruction {
+    let mut ix = build(
+        accounts::CancelLottery {
+            payer: *authority,
+            token_lottery: *token_lottery,
+            token_program: TOKEN_PROGRAM_ID,
+        },
+        instruction::CancelLottery {},
+    );
+    ix.accounts.extend(prize_accounts(prizes, None));
+    ix
+}
+
+pub fn deposit_prize(
+    depositor: &Pubkey,
+    token_lottery: &Pubkey,
+    prize_mint: &Pubkey,
+    depositor_token_account: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        accounts::DepositPrize {
+            depositor: *depositor,
+            token_lottery: *token_lottery,
+            prize_mint: *prize_mint,
+            depositor_token_account: *depositor_token_account,
+            prize_depositor: pda::prize_depositor(token_lottery, depositor).0,
+            prize: pda::prize(token_lottery, prize_mint).0,
+            prize_vault: pda::prize_vault(token_lottery, prize_mint).0,
+            token_program: TOKEN_PROGRAM_ID,
+            system_program: system_program::ID,
+        },
+        instruction::DepositPrize { amount },
+    )
+}
+
+/// Lets `depositor` escrow prizes in the lottery besides its authority.
+pub fn approve_prize_depositor(authority: &Pubkey, token_lottery: &Pubkey, depositor: &Pubkey) -> Instruction {
+    build(
+        accounts::ApprovePrizeDepositor {
+            payer: *authority,
+            token_lottery: *token_lottery,
+            prize_depositor: pda::prize_depositor(token_lottery, depositor).0,
+            system_program: system_program::ID,
+        },
+        instruction::ApprovePrizeDepositor { depositor: *depositor },
+    )
+}
+
+pub fn revoke_prize_depositor(authority: &Pubkey, token_lottery: &Pubkey, depositor: &Pubkey) -> Instruction {
+    build(
+        accounts::RevokePrizeDepositor {
+            payer: *authority,
+            token_lottery: *token_lottery,
+            prize_depositor: pda::prize_depositor(token_lottery, depositor).0,
+        },
+        instruction::RevokePrizeDepositor {},
+    )
+}
+
+fn claim_refund_accounts(
+    holder: &Pubkey,
+    lottery: &TokenLottery,
+    ticket_mint: &Pubkey,
+    referrer: Option<&Pubkey>,
+) -> accounts::ClaimRefund {
+    let token_lottery = lottery_address(lottery);
+    let collection_mint = pda::collection_mint(&lottery.authority, lottery.id).0;
+    accounts::ClaimRefund {
+        holder: *holder,
+        token_lottery,
+        ticket_mint: *ticket_mint,
+        ticket: pda::ticket(ticket_mint).0,
+        holder_token_account: pda::associated_token_account(holder, ticket_mint),
+        metadata: pda::metadata(ticket_mint).0,
+        master_edition: pda::master_edition(ticket_mint).0,
+        collection_metadata: pda::metadata(&collection_mint).0,
+        collection_mint,
+        referral: referrer.map(|referrer| pda::referral(&token_lottery, referrer).0),
+        token_program: TOKEN_PROGRAM_ID,
+        token_metadata_program: METADATA_PROGRAM_ID,
+    }
+}
+
+/// Refunds the ticket's full price, `referrer` is the ticket's
+/// `Ticket::referrer` when it paid a referral fee.
+pub fn claim_refund(
+    holder: &Pubkey,
+    lottery: &TokenLottery,
+    ticket_mint: &Pubkey,
+    referrer: Option<&Pubkey>,
+) -> Instruction {
+    build(claim_refund_accounts(holder, lottery, ticket_mint, referrer), instruction::ClaimRefund {})
+}
+
+pub fn withdraw_principal(holder: &Pubkey, lottery: &TokenLottery, ticket_mint: &Pubkey) -> Instruction {
+    build(claim_refund_accounts(holder, lottery, ticket_mint, None), instruction::WithdrawPrincipal {})
+}
+
+/// Burns a losing ticket held by `holder` once the winner is drawn.
+pub fn burn_ticket(holder: &Pubkey, lottery: &TokenLottery, ticket_mint: &Pubkey) -> Instruction {
+    let collection_mint = pda::collection_mint(&lottery.authority, lottery.id).0;
+    build(
+        accounts::BurnTicket {
+            holder: *holder,
+            token_lottery: lottery_address(lottery),
+            ticket_mint: *ticket_mint,
+            ticket: pda::ticket(ticket_mint).0,
+            holder_token_account: pda::associated_token_account(holder, ticket_mint),
+            metadata: pda::metadata(ticket_mint).0,
+            master_edition: pda::master_edition(ticket_mint).0,
+            collection_metadata: pda::metadata(&collection_mint).0,
+            collection_mint,
+            token_program: TOKEN_PROGRAM_ID,
+            token_metadata_program: METADATA_PROGRAM_ID,
+        },
+        instruction::BurnTicket {},
+    )
+}
+
+pub fn configure_no_loss(authority: &Pubkey, token_lottery: &Pubkey, is_no_loss: bool) -> Instruction {
+    build(
+        accounts::ConfigureNoLoss { payer: *authority, token_lottery: *token_lottery },
+        instruction::ConfigureNoLoss { is_no_loss },
+    )
+}
+
+/// Creates shard `shard`, which has to be the lottery's current
+/// `shard_count`.
+pub fn initialize_ticket_shard(authority: &Pubkey, token_lottery: &Pubkey, shard: u8) -> Instruction {
+    build(
+        accounts::InitializeTicketShard {
+            payer: *authority,
+            token_lottery: *token_lottery,
+            ticket_shard: pda::ticket_shard(token_lottery, shard).0,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeTicketShard { shard },
+    )
+}
+
+/// Adds every shard's sales to the lottery and closes the shards.
+pub fn finalize_shards(lottery: &TokenLottery) -> Instruction {
+    let token_lottery = lottery_address(lottery);
+    let mut ix = build(
+        accounts::FinalizeShards { token_lottery, authority: lottery.authority },
+        instruction::FinalizeShards {},
+    );
+    ix.accounts.extend(
+        (0..lottery.shard_count).map(|shard| AccountMeta::new(pda::ticket_shard(&token_lottery, shard).0, false)),
+    );
+    ix
+}
+
+pub fn reclaim_sponsorship(sponsor: &Pubkey, token_lottery: &Pubkey) -> Instruction {
+    build(
+        accounts::ReclaimSponsorship {
+            sponsor: *sponsor,
+            token_lottery: *token_lottery,
+            sponsorship: pda::sponsorship(token_lottery, sponsor).0,
+        },
+        instruction::ReclaimSponsorship {},
+    )
+}
@@ -0,0 +1,102 @@
+//! Client for the `tokenlottery` program: PDA derivation, instruction
+//! builders and account decoders, built on the program crate with its
+//! `no-entrypoint` feature.
+
+use anchor_lang::prelude::Pubkey;
+use tokenlottery::TokenLottery;
+
+pub mod instructions;
+pub mod pda;
+pub mod state;
+
+pub use tokenlottery::{self as program, ID as PROGRAM_ID};
+
+pub const TOKEN_PROGRAM_ID: Pubkey = anchor_spl::token::ID;
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey = anchor_spl::associated_token::ID;
+pub const METADATA_PROGRAM_ID: Pubkey = anchor_spl::metadata::mpl_token_metadata::ID;
+
+/// Address of a decoded `TokenLottery` account.
+pub fn lottery_address(lottery: &TokenLottery) -> Pubkey {
+    pda::token_lottery(&lottery.authority, lottery.id).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::{AccountSerialize, Discriminator};
+    use tokenlottery::{BondingCurve, PurchaseGate};
+
+    fn lottery(authority: Pubkey) -> TokenLottery {
+        TokenLottery {
+            version: tokenlottery::TOKEN_LOTTERY_VERSION,
+            id: 3,
+            bump: pda::token_lottery(&authority, 3).1,
+            winner: 0,
+            is_winner_chosen: false,
+            lottery_start: 0,
+            lottery_end: 100,
+            price: 10_000,
+            number_of_tickets: 2,
+            lottery_pot_amount: 20_000,
+            authority,
+            randomness_account: Pubkey::new_unique(),
+            sponsor_amount: 0,
+            is_cancelled: false,
+            min_tickets: 1,
+            prize_count: 0,
+            price_tiers: vec![],
+            bonding_curve: BondingCurve::Flat,
+            allowlist_root: [0; 32],
+            presale_start: 0,
+            presale_end: 0,
+            purchase_gate: PurchaseGate::None,
+            referral_bps: 0,
+            referral_total: 0,
+            promo_tickets: 0,
+            max_promo_tickets: None,
+            is_weighted: false,
+            is_no_loss: false,
+            principal_amount: 0,
+            is_prize_claimed: false,
+            collection_mint_bump: 0,
+            shard_count: 0,
+            is_shards_finalized: false,
+            shard_tickets: [0; tokenlottery::MAX_TICKET_SHARDS],
+            ticket_freeze: tokenlottery::TicketFreeze::Transferable,
+            v1_tickets: 0,
+            reserved: [0; 52],
+        }
+    }
+
+    #[test]
+    fn decodes_serialized_lottery() {
+        let lottery = lottery(Pubkey::new_unique());
+        let mut data = Vec::new();
+        lottery.try_serialize(&mut data).unwrap();
+
+        let decoded = state::decode_token_lottery(&data).unwrap();
+        assert_eq!(decoded.authority, lottery.authority);
+        assert_eq!(decoded.number_of_tickets, 2);
+    }
+
+    #[test]
+    fn buy_ticket_resolves_next_ticket_accounts() {
+        let authority = Pubkey::new_unique();
+        let buyer = Pubkey::new_unique();
+        let lottery = lottery(authority);
+
+        let buy = instructions::BuyTicket::new(&buyer, &lottery);
+        let ix = buy.instruction(10_000);
+
+        let ticket_mint = pda::ticket_mint(&buyer, 3, 2).0;
+        assert_eq!(buy.ticket_mint(), ticket_mint);
+        assert_eq!(ix.program_id, PROGRAM_ID);
+        assert_eq!(ix.data[..8], tokenlottery::instruction::BuyTicket::DISCRIMINATOR);
+        assert_eq!(ix.accounts[3].pubkey, pda::token_lottery(&authority, 3).0);
+        assert_eq!(ix.accounts[5].pubkey, ticket_mint);
+        assert_eq!(ix.accounts[6].pubkey, pda::associated_token_account(&buyer, &ticket_mint));
+        assert_eq!(ix.accounts[11].pubkey, pda::collection_mint(&authority, 3).0);
+        // unused optional accounts are passed as the program id
+        assert!(ix.accounts[12..16].iter().all(|meta| meta.pubkey == PROGRAM_ID));
+    }
+}
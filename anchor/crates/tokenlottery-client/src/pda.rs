@@ -0,0 +1,142 @@
+//! Addresses of every account the program derives from seeds.
+//!
+//! Each helper returns the address together with its bump, mirroring
+//! `Pubkey::find_program_address`.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+
+use crate::{METADATA_PROGRAM_ID, TOKEN_PROGRAM_ID};
+
+pub fn registry() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"registry"], &tokenlottery::ID)
+}
+
+pub fn authority_index(authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"authority_index", authority.as_ref()], &tokenlottery::ID)
+}
+
+/// Entry `index` of the lotteries created by `authority`.
+pub fn authority_lottery(authority: &Pubkey, index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"authority_lottery", authority.as_ref(), &index.to_le_bytes()],
+        &tokenlottery::ID,
+    )
+}
+
+pub fn token_lottery(authority: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"token_lottery", authority.as_ref(), &id.to_le_bytes()],
+        &tokenlottery::ID,
+    )
+}
+
+pub fn collection_mint(authority: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"collection_mint", authority.as_ref(), &id.to_le_bytes()],
+        &tokenlottery::ID,
+    )
+}
+
+pub fn collection_token_account(authority: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"collection_token_account", authority.as_ref(), &id.to_le_bytes()],
+        &tokenlottery::ID,
+    )
+}
+
+/// Mint of the ticket `buyer` buys as ticket number `index` of lottery `id`.
+pub fn ticket_mint(buyer: &Pubkey, id: u64, index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"ticket_mint", buyer.as_ref(), &id.to_le_bytes(), &index.to_le_bytes()],
+        &tokenlottery::ID,
+    )
+}
+
+pub fn ticket(ticket_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"ticket", ticket_mint.as_ref()], &tokenlottery::ID)
+}
+
+/// Wallet the ticket's holder designated to receive its prize.
+pub fn prize_recipient(ticket_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"prize_recipient", ticket_mint.as_ref()], &tokenlottery::ID)
+}
+
+pub fn entry_weights(token_lottery: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"entry_weights", token_lottery.as_ref()], &tokenlottery::ID)
+}
+
+/// Shard number `shard` of the lottery's sharded ticket sales.
+pub fn ticket_shard(token_lottery: &Pubkey, shard: u8) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"ticket_shard", token_lottery.as_ref(), &shard.to_le_bytes()],
+        &tokenlottery::ID,
+    )
+}
+
+pub fn draw_result(token_lottery: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"draw_result", token_lottery.as_ref()], &tokenlottery::ID)
+}
+
+pub fn referral(token_lottery: &Pubkey, referrer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"referral", token_lottery.as_ref(), referrer.as_ref()],
+        &tokenlottery::ID,
+    )
+}
+
+pub fn allowlist_usage(token_lottery: &Pubkey, buyer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"allowlist", token_lottery.as_ref(), buyer.as_ref()],
+        &tokenlottery::ID,
+    )
+}
+
+pub fn sponsorship(token_lottery: &Pubkey, sponsor: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"sponsorship", token_lottery.as_ref(), sponsor.as_ref()],
+        &tokenlottery::ID,
+    )
+}
+
+pub fn prize(token_lottery: &Pubkey, prize_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"prize", token_lottery.as_ref(), prize_mint.as_ref()],
+        &tokenlottery::ID,
+    )
+}
+
+pub fn prize_depositor(token_lottery: &Pubkey, depositor: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"prize_depositor", token_lottery.as_ref(), depositor.as_ref()],
+        &tokenlottery::ID,
+    )
+}
+
+pub fn prize_vault(token_lottery: &Pubkey, prize_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"prize_vault", token_lottery.as_ref(), prize_mint.as_ref()],
+        &tokenlottery::ID,
+    )
+}
+
+/// Metaplex metadata account of `mint`.
+pub fn metadata(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"metadata", METADATA_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &METADATA_PROGRAM_ID,
+    )
+}
+
+/// Metaplex master edition account of `mint`.
+pub fn master_edition(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"metadata", METADATA_PROGRAM_ID.as_ref(), mint.as_ref(), b"edition"],
+        &METADATA_PROGRAM_ID,
+    )
+}
+
+/// Associated token account of `owner` for `mint` under the SPL token program.
+pub fn associated_token_account(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(owner, mint, &TOKEN_PROGRAM_ID)
+}
@@ -0,0 +1,35 @@
+//! Decoders for the program's accounts.
+
+use anchor_lang::{AccountDeserialize, Discriminator};
+use tokenlottery::{EntryWeights, TokenLottery, TOKEN_LOTTERY_VERSION};
+
+pub use tokenlottery::{
+    AllowlistUsage, AuthorityIndex, AuthorityLottery, DrawResult, Prize, PrizeDepositor, Referral,
+    Registry, PrizeRecipient, Sponsorship, Ticket, TicketShard,
+};
+
+/// Decodes any `#[account]` of the program, discriminator included.
+pub fn decode<T: AccountDeserialize>(data: &[u8]) -> anchor_lang::Result<T> {
+    T::try_deserialize(&mut &data[..])
+}
+
+/// Decodes a `TokenLottery`, upgrading accounts that have not been through
+/// `migrate_lottery` yet to the current layout.
+pub fn decode_token_lottery(data: &[u8]) -> anchor_lang::Result<TokenLottery> {
+    match decode::<TokenLottery>(data) {
+        Ok(lottery) if lottery.version == TOKEN_LOTTERY_VERSION => Ok(lottery),
+        _ => TokenLottery::from_v1_data(data),
+    }
+}
+
+/// Copies the zero-copy `EntryWeights` account out of `data`, which does not
+/// need to be aligned.
+pub fn decode_entry_weights(data: &[u8]) -> anchor_lang::Result<EntryWeights> {
+    if data.len() < 8 + std::mem::size_of::<EntryWeights>() {
+        return Err(anchor_lang::error::ErrorCode::AccountDidNotDeserialize.into());
+    }
+    if data[..8] != EntryWeights::DISCRIMINATOR {
+        return Err(anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch.into());
+    }
+    Ok(bytemuck::pod_read_unaligned(&data[8..8 + std::mem::size_of::<EntryWeights>()]))
+}
@@ -0,0 +1,75 @@
+//! How `choose_a_winner` turns the revealed Switchboard value into a winning
+//! ticket index.
+//!
+//! The program calls these functions itself, so anyone holding the revealed
+//! bytes and the lottery's ticket count (or entry weights) can recompute the
+//! winner without trusting the program's output.
+
+/// Winner of an unweighted lottery: the first eight revealed bytes, read
+/// little endian, modulo the number of tickets. `None` when no tickets were
+/// sold.
+pub fn uniform_winner(revealed: &[u8; 32], number_of_tickets: u64) -> Option<u64> {
+    (number_of_tickets > 0).then(|| revealed_u64(revealed) % number_of_tickets)
+}
+
+/// Point drawn on the cumulative weight line of a weighted lottery: the
+/// first eight revealed bytes, read little endian, modulo the total weight.
+/// `None` when the total weight is zero.
+pub fn weighted_target(revealed: &[u8; 32], total_weight: u64) -> Option<u64> {
+    (total_weight > 0).then(|| revealed_u64(revealed) % total_weight)
+}
+
+fn revealed_u64(revealed: &[u8; 32]) -> u64 {
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&revealed[..8]);
+    u64::from_le_bytes(value)
+}
+
+/// Winner of a weighted lottery with entry `i` weighing `weights[i]`: the
+/// entry whose slice of the cumulative weight line covers `weighted_target`.
+/// `None` when the weights are all zero or their sum overflows.
+pub fn weighted_winner(revealed: &[u8; 32], weights: &[u64]) -> Option<u64> {
+    let total = weights.iter().try_fold(0u64, |sum, weight| sum.checked_add(*weight))?;
+    let target = weighted_target(revealed, total)?;
+
+    let mut end = 0u64;
+    for (index, weight) in weights.iter().enumerate() {
+        end += weight;
+        if target < end {
+            return Some(index as u64);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revealed(prefix: &[u8]) -> [u8; 32] {
+        let mut revealed = [0xff; 32];
+        revealed[..prefix.len()].copy_from_slice(prefix);
+        revealed
+    }
+
+    #[test]
+    fn uniform_uses_the_first_eight_bytes() {
+        assert_eq!(uniform_winner(&revealed(&[7, 0, 0, 0, 0, 0, 0, 0]), 5), Some(2));
+        assert_eq!(uniform_winner(&revealed(&[7, 0, 0, 0, 0, 0, 0, 0]), 300), Some(7));
+        // 0x0107 = 263, past what a single byte can pick
+        assert_eq!(uniform_winner(&revealed(&[7, 1, 0, 0, 0, 0, 0, 0]), 300), Some(263));
+        // the ninth byte onwards never matter
+        assert_eq!(uniform_winner(&revealed(&[7, 1, 0, 0, 0, 0, 0, 0, 0]), 300), Some(263));
+        assert_eq!(uniform_winner(&revealed(&[7]), 0), None);
+    }
+
+    #[test]
+    fn weighted_skips_zero_weight_entries() {
+        let weights = [3, 0, 2, 5];
+        // targets 0..3 hit entry 0, 3..5 entry 2 and 5..10 entry 3
+        assert_eq!(weighted_winner(&revealed(&[2, 0, 0, 0, 0, 0, 0, 0]), &weights), Some(0));
+        assert_eq!(weighted_winner(&revealed(&[3, 0, 0, 0, 0, 0, 0, 0]), &weights), Some(2));
+        assert_eq!(weighted_winner(&revealed(&[19, 0, 0, 0, 0, 0, 0, 0]), &weights), Some(3));
+        assert_eq!(weighted_winner(&revealed(&[1]), &[0, 0]), None);
+    }
+}
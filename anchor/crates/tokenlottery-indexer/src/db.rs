@@ -0,0 +1,364 @@
+//! SQLite schema and writes.
+//!
+//! Every row is keyed by what identifies it on chain (lottery address, ticket
+//! index, transaction signature), and writes either ignore or update
+//! existing rows, so the same transactions can be ingested any number of
+//! times.
+
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use solana_sdk::pubkey::Pubkey;
+use tokenlottery::TokenLottery;
+
+use crate::events::Event;
+use crate::source::RawTransaction;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT PRIMARY KEY,
+    slot INTEGER NOT NULL,
+    block_time INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS lotteries (
+    address TEXT PRIMARY KEY,
+    id INTEGER NOT NULL,
+    authority TEXT NOT NULL,
+    lottery_start INTEGER NOT NULL,
+    lottery_end INTEGER NOT NULL,
+    price INTEGER NOT NULL,
+    min_tickets INTEGER NOT NULL,
+    number_of_tickets INTEGER NOT NULL DEFAULT 0,
+    lottery_pot_amount INTEGER,
+    winner INTEGER,
+    is_cancelled INTEGER NOT NULL DEFAULT 0,
+    created_signature TEXT,
+    snapshot_slot INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS tickets (
+    lottery TEXT NOT NULL,
+    ticket_index INTEGER NOT NULL,
+    ticket_mint TEXT NOT NULL,
+    buyer TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    price_paid INTEGER NOT NULL,
+    referral_fee INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    signature TEXT NOT NULL,
+    PRIMARY KEY (lottery, ticket_index)
+);
+
+CREATE TABLE IF NOT EXISTS draws (
+    lottery TEXT PRIMARY KEY,
+    winner INTEGER NOT NULL,
+    number_of_tickets INTEGER NOT NULL,
+    randomness_account TEXT NOT NULL,
+    revealed_value BLOB NOT NULL,
+    slot INTEGER NOT NULL,
+    signature TEXT NOT NULL
+);
+
+-- prize payouts and refunds, `kind` is 'prize' or 'refund'
+CREATE TABLE IF NOT EXISTS claims (
+    signature TEXT NOT NULL,
+    ticket_mint TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    lottery TEXT NOT NULL,
+    claimant TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    PRIMARY KEY (signature, ticket_mint, kind)
+);
+";
+
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Most recent ingested signature, where RPC ingestion resumes from.
+    pub fn latest_signature(&self) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row("SELECT signature FROM transactions ORDER BY slot DESC LIMIT 1", [], |row| row.get(0))
+            .optional()?)
+    }
+
+    /// Writes a transaction and its events atomically.
+    pub fn ingest(&mut self, raw: &RawTransaction, events: &[Event]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO transactions (signature, slot, block_time) VALUES (?1, ?2, ?3)",
+            params![raw.signature, raw.slot, raw.block_time],
+        )?;
+
+        for event in events {
+            match event {
+                Event::LotteryCreated(event) => {
+                    tx.execute(
+                        "INSERT INTO lotteries
+                            (address, id, authority, lottery_start, lottery_end, price, min_tickets, created_signature)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                         ON CONFLICT (address) DO UPDATE SET created_signature = excluded.created_signature",
+                        params![
+                            event.lottery.to_string(),
+                            event.id,
+                            event.authority.to_string(),
+                            event.lottery_start,
+                            event.lottery_end,
+                            event.price,
+                            event.min_tickets,
+                            raw.signature,
+                        ],
+                    )?;
+                }
+                Event::TicketPurchased(event) => {
+                    let inserted = tx.execute(
+                        "INSERT OR IGNORE INTO tickets
+                            (lottery, ticket_index, ticket_mint, buyer, recipient, price_paid, referral_fee, slot, block_time, signature)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                        params![
+                            event.lottery.to_string(),
+                            event.index,
+                            event.ticket_mint.to_string(),
+                            event.buyer.to_string(),
+                            event.recipient.to_string(),
+                            event.price_paid,
+                            event.referral_fee,
+                            event.slot,
+                            raw.block_time,
+                            raw.signature,
+                        ],
+                    )?;
+                    if inserted > 0 {
+                        // shard tickets are indexed from `TICKETS_PER_SHARD`,
+                        // so count the rows rather than going by the index
+                        tx.execute(
+                            "UPDATE lotteries SET number_of_tickets =
+                                max(number_of_tickets, (SELECT count(*) FROM tickets WHERE lottery = ?1))
+                             WHERE address = ?1",
+                            params![event.lottery.to_string()],
+                        )?;
+                    }
+                }
+                Event::WinnerChosen(event) => {
+                    tx.execute(
+                        "INSERT OR IGNORE INTO draws
+                            (lottery, winner, number_of_tickets, randomness_account, revealed_value, slot, signature)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![
+                            event.lottery.to_string(),
+                            event.winner,
+                            event.number_of_tickets,
+                            event.randomness_account.to_string(),
+                            &event.revealed_value[..],
+                            event.slot,
+                            raw.signature,
+                        ],
+                    )?;
+                    tx.execute(
+                        "UPDATE lotteries SET winner = ?2 WHERE address = ?1",
+                        params![event.lottery.to_string(), event.winner],
+                    )?;
+                }
+                Event::PrizeClaimed(event) => {
+                    insert_claim(&tx, raw, "prize", &event.lottery, &event.ticket_mint, &event.winner, event.amount)?;
+                }
+                Event::TicketRefunded(event) => {
+                    insert_claim(&tx, raw, "refund", &event.lottery, &event.ticket_mint, &event.holder, event.amount)?;
+                }
+                Event::LotteryCancelled(event) => {
+                    tx.execute(
+                        "UPDATE lotteries SET is_cancelled = 1 WHERE address = ?1",
+                        params![event.lottery.to_string()],
+                    )?;
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Overwrites a lottery's state with its account, creating the row for
+    /// lotteries whose creation was never seen in the logs. Older snapshots
+    /// than the stored one are ignored.
+    pub fn snapshot(&self, address: &Pubkey, lottery: &TokenLottery, slot: u64) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO lotteries
+                (address, id, authority, lottery_start, lottery_end, price, min_tickets,
+                 number_of_tickets, lottery_pot_amount, winner, is_cancelled, snapshot_slot)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+             ON CONFLICT (address) DO UPDATE SET
+                lottery_start = excluded.lottery_start,
+                lottery_end = excluded.lottery_end,
+                price = excluded.price,
+                min_tickets = excluded.min_tickets,
+                number_of_tickets = excluded.number_of_tickets,
+                lottery_pot_amount = excluded.lottery_pot_amount,
+                winner = excluded.winner,
+                is_cancelled = excluded.is_cancelled,
+                snapshot_slot = excluded.snapshot_slot
+             WHERE snapshot_slot IS NULL OR snapshot_slot <= excluded.snapshot_slot",
+            params![
+                address.to_string(),
+                lottery.id,
+                lottery.authority.to_string(),
+                lottery.lottery_start,
+                lottery.lottery_end,
+                lottery.price,
+                lottery.min_tickets,
+                lottery.number_of_tickets,
+                lottery.lottery_pot_amount,
+                lottery.is_winner_chosen.then_some(lottery.winner),
+                lottery.is_cancelled,
+                slot,
+            ],
+        )?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn count(&self, table: &str) -> i64 {
+        self.conn
+            .query_row(&format!("SELECT count(*) FROM {table}"), [], |row| row.get(0))
+            .unwrap()
+    }
+}
+
+fn insert_claim(
+    tx: &rusqlite::Transaction,
+    raw: &RawTransaction,
+    kind: &str,
+    lottery: &Pubkey,
+    ticket_mint: &Pubkey,
+    claimant: &Pubkey,
+    amount: u64,
+) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO claims (signature, ticket_mint, kind, lottery, claimant, amount, slot, block_time)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            raw.signature,
+            ticket_mint.to_string(),
+            kind,
+            lottery.to_string(),
+            claimant.to_string(),
+            amount,
+            raw.slot,
+            raw.block_time,
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{parse_logs, tests::data_line};
+    use tokenlottery::{LotteryCreated, TicketPurchased};
+
+    #[test]
+    fn reingesting_a_transaction_changes_nothing() {
+        let lottery = Pubkey::new_unique();
+        let buyer = Pubkey::new_unique();
+        let program = tokenlottery::ID;
+        let raw = RawTransaction {
+            signature: "sig".to_owned(),
+            slot: 42,
+            block_time: Some(1_700_000_000),
+            logs: vec![
+                format!("Program {program} invoke [1]"),
+                data_line(&LotteryCreated {
+                    lottery,
+                    id: 0,
+                    authority: buyer,
+                    lottery_start: 0,
+                    lottery_end: 100,
+                    price: 10_000,
+                    min_tickets: 1,
+                }),
+                data_line(&TicketPurchased {
+                    lottery,
+                    ticket_mint: Pubkey::new_unique(),
+                    index: 0,
+                    buyer,
+                    recipient: buyer,
+                    price_paid: 10_000,
+                    referral_fee: 0,
+                    slot: 42,
+                }),
+                format!("Program {program} success"),
+            ],
+        };
+
+        let mut store = Store::open(":memory:").unwrap();
+        let events = parse_logs(&program, &raw.logs);
+        store.ingest(&raw, &events).unwrap();
+        store.ingest(&raw, &events).unwrap();
+
+        assert_eq!(store.count("transactions"), 1);
+        assert_eq!(store.count("lotteries"), 1);
+        assert_eq!(store.count("tickets"), 1);
+        let tickets: i64 = store
+            .conn
+            .query_row("SELECT number_of_tickets FROM lotteries", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tickets, 1);
+    }
+
+    #[test]
+    fn counts_shard_indexed_tickets_once() {
+        let lottery = Pubkey::new_unique();
+        let buyer = Pubkey::new_unique();
+        let program = tokenlottery::ID;
+        let raw = RawTransaction {
+            signature: "sig".to_owned(),
+            slot: 42,
+            block_time: Some(1_700_000_000),
+            logs: vec![
+                format!("Program {program} invoke [1]"),
+                data_line(&LotteryCreated {
+                    lottery,
+                    id: 0,
+                    authority: buyer,
+                    lottery_start: 0,
+                    lottery_end: 100,
+                    price: 10_000,
+                    min_tickets: 1,
+                }),
+                data_line(&TicketPurchased {
+                    lottery,
+                    ticket_mint: Pubkey::new_unique(),
+                    index: tokenlottery::TICKETS_PER_SHARD,
+                    buyer,
+                    recipient: buyer,
+                    price_paid: 10_000,
+                    referral_fee: 0,
+                    slot: 42,
+                }),
+                format!("Program {program} success"),
+            ],
+        };
+
+        let mut store = Store::open(":memory:").unwrap();
+        let events = parse_logs(&program, &raw.logs);
+        store.ingest(&raw, &events).unwrap();
+        store.ingest(&raw, &events).unwrap();
+
+        let tickets: i64 = store
+            .conn
+            .query_row("SELECT number_of_tickets FROM lotteries", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tickets, 1);
+    }
+}
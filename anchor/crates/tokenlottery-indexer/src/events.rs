@@ -0,0 +1,92 @@
+//! Decoding of the program's `emit!` events from transaction logs.
+
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use solana_sdk::pubkey::Pubkey;
+use tokenlottery::{LotteryCancelled, LotteryCreated, PrizeClaimed, TicketPurchased, TicketRefunded, WinnerChosen};
+
+pub enum Event {
+    LotteryCreated(LotteryCreated),
+    TicketPurchased(TicketPurchased),
+    WinnerChosen(WinnerChosen),
+    PrizeClaimed(PrizeClaimed),
+    TicketRefunded(TicketRefunded),
+    LotteryCancelled(LotteryCancelled),
+}
+
+/// Events emitted by `program` in a transaction's log messages, in order.
+///
+/// Only `Program data:` lines logged while `program` is the innermost
+/// invoked program are considered, so events logged by other programs or
+/// unknown payloads are skipped.
+pub fn parse_logs(program: &Pubkey, logs: &[String]) -> Vec<Event> {
+    let program = program.to_string();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs {
+        let Some(rest) = line.strip_prefix("Program ") else { continue };
+        if let Some(data) = rest.strip_prefix("data: ") {
+            if stack.last() == Some(&program.as_str()) {
+                events.extend(decode(data));
+            }
+        } else if let Some((id, status)) = rest.split_once(' ') {
+            if status.starts_with("invoke [") {
+                stack.push(id);
+            } else if status == "success" || status.starts_with("failed") {
+                stack.pop();
+            }
+        }
+    }
+    events
+}
+
+fn decode(data: &str) -> Option<Event> {
+    let bytes = STANDARD.decode(data).ok()?;
+    if bytes.len() < 8 {
+        return None;
+    }
+    let (discriminator, mut payload) = bytes.split_at(8);
+
+    macro_rules! try_event {
+        ($($event:ident),*) => {
+            $(
+                if discriminator == $event::DISCRIMINATOR {
+                    return $event::deserialize(&mut payload).ok().map(Event::$event);
+                }
+            )*
+        };
+    }
+    try_event!(LotteryCreated, TicketPurchased, WinnerChosen, PrizeClaimed, TicketRefunded, LotteryCancelled);
+    None
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// `Program data:` line `emit!` logs for `event`.
+    pub fn data_line(event: &impl anchor_lang::Event) -> String {
+        format!("Program data: {}", STANDARD.encode(event.data()))
+    }
+
+    #[test]
+    fn only_decodes_events_of_the_program() {
+        let program = tokenlottery::ID;
+        let other = Pubkey::new_unique();
+        let cancelled = LotteryCancelled { lottery: Pubkey::new_unique() };
+        let logs = vec![
+            format!("Program {program} invoke [1]"),
+            format!("Program {other} invoke [2]"),
+            data_line(&cancelled),
+            format!("Program {other} success"),
+            "Program data: bm90IGFuIGV2ZW50".to_owned(),
+            data_line(&cancelled),
+            format!("Program {program} success"),
+        ];
+
+        let events = parse_logs(&program, &logs);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], Event::LotteryCancelled(event) if event.lottery == cancelled.lottery));
+    }
+}
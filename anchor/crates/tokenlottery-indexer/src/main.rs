@@ -0,0 +1,81 @@
+//! Materialises lottery history into SQLite for dashboards.
+//!
+//! Events are decoded from the `Program data:` log lines of the program's
+//! transactions, read from an RPC node or a JSON fixture. Logs can be
+//! truncated and lotteries created before events existed never emitted
+//! `LotteryCreated`, so lottery rows are also refreshed from `TokenLottery`
+//! account snapshots.
+
+use anyhow::Result;
+use clap::Parser;
+use solana_sdk::pubkey::Pubkey;
+
+mod db;
+mod events;
+mod source;
+
+#[derive(Parser)]
+#[command(name = "tokenlottery-indexer", about = "Index tokenlottery history into SQLite")]
+struct Cli {
+    /// SQLite database, created if missing
+    #[arg(long, default_value = "tokenlottery.sqlite")]
+    db: String,
+
+    /// Program to index
+    #[arg(long, default_value_t = tokenlottery::ID)]
+    program: Pubkey,
+
+    /// RPC endpoint to ingest from
+    #[arg(long, short = 'u', env = "TOKENLOTTERY_RPC_URL", conflicts_with = "fixture", required_unless_present = "fixture")]
+    url: Option<String>,
+
+    /// JSON fixture to ingest instead of an RPC node
+    #[arg(long)]
+    fixture: Option<String>,
+
+    /// Skip the account snapshots
+    #[arg(long)]
+    no_snapshots: bool,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let mut store = db::Store::open(&cli.db)?;
+
+    let (transactions, snapshots) = match (&cli.fixture, cli.url) {
+        (Some(path), _) => {
+            let fixture = source::Fixture::load(path)?;
+            let lotteries = fixture.lotteries()?;
+            (fixture.transactions, (fixture.slot, lotteries))
+        }
+        (None, Some(url)) => {
+            let rpc = source::Rpc::new(url, cli.program);
+            let transactions = rpc.transactions(store.latest_signature()?.as_deref())?;
+            let snapshots = if cli.no_snapshots { (0, Vec::new()) } else { rpc.lotteries()? };
+            (transactions, snapshots)
+        }
+        (None, None) => unreachable!("clap requires --url or --fixture"),
+    };
+
+    let mut event_count = 0;
+    for raw in &transactions {
+        let events = events::parse_logs(&cli.program, &raw.logs);
+        event_count += events.len();
+        store.ingest(raw, &events)?;
+    }
+
+    let (slot, lotteries) = snapshots;
+    if !cli.no_snapshots {
+        for (address, lottery) in &lotteries {
+            store.snapshot(address, lottery, slot)?;
+        }
+    }
+
+    println!(
+        "ingested {} transactions, {} events, {} lottery snapshots",
+        transactions.len(),
+        event_count,
+        if cli.no_snapshots { 0 } else { lotteries.len() }
+    );
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! Where transactions and account snapshots come from: an RPC node or a
+//! JSON fixture with the same content.
+
+use std::str::FromStr;
+
+use anchor_lang::Discriminator;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcTransactionConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{option_serializer::OptionSerializer, UiTransactionEncoding};
+use tokenlottery::TokenLottery;
+
+#[derive(Deserialize)]
+pub struct RawTransaction {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub logs: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RawAccount {
+    pub address: String,
+    /// base64 encoded account data
+    pub data: String,
+}
+
+/// Content of a fixture file, transactions in the order they executed.
+#[derive(Deserialize)]
+pub struct Fixture {
+    #[serde(default)]
+    pub slot: u64,
+    #[serde(default)]
+    pub transactions: Vec<RawTransaction>,
+    #[serde(default)]
+    pub accounts: Vec<RawAccount>,
+}
+
+impl Fixture {
+    pub fn load(path: &str) -> Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("opening fixture {path}"))?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    pub fn lotteries(&self) -> Result<Vec<(Pubkey, TokenLottery)>> {
+        self.accounts
+            .iter()
+            .map(|account| {
+                let data = STANDARD.decode(&account.data)?;
+                Ok((Pubkey::from_str(&account.address)?, tokenlottery_client::state::decode_token_lottery(&data)?))
+            })
+            .collect()
+    }
+}
+
+pub struct Rpc {
+    client: RpcClient,
+    program: Pubkey,
+}
+
+impl Rpc {
+    pub fn new(url: String, program: Pubkey) -> Self {
+        Self { client: RpcClient::new_with_commitment(url, CommitmentConfig::confirmed()), program }
+    }
+
+    /// Successful transactions of the program after `until`, oldest first.
+    pub fn transactions(&self, until: Option<&str>) -> Result<Vec<RawTransaction>> {
+        let until = until.map(Signature::from_str).transpose()?;
+        let mut signatures = Vec::new();
+        let mut before = None;
+        loop {
+            let page = self.client.get_signatures_for_address_with_config(
+                &self.program,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until,
+                    limit: None,
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )?;
+            let Some(last) = page.last() else { break };
+            before = Some(Signature::from_str(&last.signature)?);
+            signatures.extend(page.into_iter().filter(|status| status.err.is_none()));
+        }
+
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Json),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        };
+        signatures
+            .into_iter()
+            .rev()
+            .map(|status| {
+                let tx = self.client.get_transaction_with_config(&Signature::from_str(&status.signature)?, config)?;
+                let logs = match tx.transaction.meta.map(|meta| meta.log_messages) {
+                    Some(OptionSerializer::Some(logs)) => logs,
+                    _ => Vec::new(),
+                };
+                Ok(RawTransaction { signature: status.signature, slot: tx.slot, block_time: tx.block_time, logs })
+            })
+            .collect()
+    }
+
+    /// Every `TokenLottery` account and the slot they were read at.
+    pub fn lotteries(&self) -> Result<(u64, Vec<(Pubkey, TokenLottery)>)> {
+        let slot = self.client.get_slot()?;
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, TokenLottery::DISCRIMINATOR.to_vec()))]),
+            account_config: RpcAccountInfoConfig { encoding: Some(UiAccountEncoding::Base64), ..Default::default() },
+            ..Default::default()
+        };
+        let lotteries = self
+            .client
+            .get_program_accounts_with_config(&self.program, config)?
+            .into_iter()
+            .map(|(address, account)| Ok((address, tokenlottery_client::state::decode_token_lottery(&account.data)?)))
+            .collect::<Result<_>>()?;
+        Ok((slot, lotteries))
+    }
+}
@@ -0,0 +1,634 @@
+#![no_main]
+//! Runs random sequences of every tokenlottery instruction, with random
+//! signers, slots and tickets, against the compiled program in LiteSVM.
+//!
+//! After each step the harness checks that:
+//! - the program never panics, successful or not;
+//! - the lottery and every ticket shard hold exactly their rent plus their pot;
+//! - the pots of the lottery and its shards add up to the payments into the
+//!   pot (ticket principals and sponsorships) minus the payouts (refunds,
+//!   reclaimed sponsorships and the winner's payout);
+//! - the pot is paid out at most once and no ticket is refunded twice;
+//! - ticket purchases and promo tickets stay within `MINT_TICKET_COMPUTE_UNITS`;
+//! - randomness accounts not owned by the Switchboard program are rejected;
+//! - a lottery's committed randomness is never replaced by `commit_a_winner`.
+//!
+//! Needs `anchor build` to have produced `target/deploy/tokenlottery.so`, and
+//! the Metaplex token metadata program dumped to
+//! `fuzz/fixtures/mpl_token_metadata.so`, e.g. with
+//! `solana program dump -um metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s fuzz/fixtures/mpl_token_metadata.so`.
+//! Run from `anchor/` with `cargo +nightly fuzz run instruction_sequence`, or
+//! with `fuzz/run.sh`, which does both steps first.
+
+use std::collections::HashMap;
+
+use anchor_lang::{solana_program::keccak, AccountDeserialize, Space};
+use anchor_spl::token::spl_token;
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use litesvm::LiteSVM;
+use solana_sdk::{
+    account::Account,
+    clock::Clock,
+    compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use switchboard_on_demand::{accounts::RandomnessAccountData, Discriminator as _};
+use tokenlottery::{
+    BondingCurve, PriceTier, Prize, PurchaseGate, Ticket, TicketFreeze, TicketShard, TokenLottery, SWITCHBOARD_PROGRAM_ID,
+};
+use tokenlottery_client::{instructions, pda, METADATA_PROGRAM_ID, TOKEN_PROGRAM_ID};
+
+const PROGRAM_SO: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../target/deploy/tokenlottery.so");
+const METADATA_SO: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/mpl_token_metadata.so");
+
+const USERS: usize = 4;
+const AUTHORITY: usize = 0;
+const START_SLOT: u64 = 10;
+const PRIZE_BALANCE: u64 = 1_000_000;
+// compute budget of every instruction minting a ticket, the limit clients set
+const MINT_TICKET_COMPUTE_UNITS: u64 = 200_000;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    duration: u8,
+    price: u16,
+    min_tickets: u8,
+    actions: Vec<Action>,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Action {
+    Warp { slots: u8 },
+    MigrateLottery { signer: u8 },
+    ConfigurePricing { signer: u8, tiers: Vec<(u8, u16)>, curve: u8, param: u16 },
+    BuyTicket { signer: u8, recipient: u8, slack: u16, referrer: Option<u8>, recipient_signs: bool },
+    BuyTicketAllowlisted { signer: u8, recipient: u8, slack: u16, max_allowance: u8 },
+    IssueTicket { recipient: u8 },
+    ConfigurePromo { signer: u8, max_promo_tickets: Option<u8> },
+    InitializeEntryWeights { signer: u8 },
+    SetEntryWeight { signer: u8, index: u8, weight: u16 },
+    ConfigureGate { signer: u8, kind: u8, holder: u8 },
+    ConfigureReferrals { signer: u8, referral_bps: u16 },
+    RegisterReferrer { signer: u8 },
+    ClaimReferralRewards { signer: u8 },
+    ConfigureAllowlist { signer: u8, allowed: u8, max_allowance: u8, presale_len: u8 },
+    CommitAWinner { signer: u8 },
+    ChooseAWinner { signer: u8, value: [u8; 32] },
+    ForgeRandomness { signer: u8, value: [u8; 32], draw: bool },
+    CloseLottery { signer: u8 },
+    ClaimPrize { signer: u8, ticket: u8 },
+    ClaimPrizeFor { signer: u8, ticket: u8, holder: u8, recipient: Option<u8> },
+    DesignatePrizeRecipient { signer: u8, ticket: u8, recipient: u8 },
+    FundPot { signer: u8, amount: u32 },
+    CancelLottery { signer: u8 },
+    DepositPrize { signer: u8, amount: u32 },
+    ApprovePrizeDepositor { signer: u8, depositor: u8 },
+    RevokePrizeDepositor { signer: u8, depositor: u8 },
+    ClaimRefund { signer: u8, ticket: u8 },
+    WithdrawPrincipal { signer: u8, ticket: u8 },
+    ConfigureNoLoss { signer: u8, is_no_loss: bool },
+    ReclaimSponsorship { signer: u8 },
+    InitializeTicketShard { signer: u8, shard: u8 },
+    BuyTicketSharded { signer: u8, recipient: u8, slack: u16, shard: u8, referrer: Option<u8> },
+    FinalizeShards,
+    BurnTicket { signer: u8, ticket: u8 },
+    ConfigureTicketFreeze { signer: u8, kind: u8 },
+}
+
+struct TicketModel {
+    mint: Pubkey,
+    principal: u64,
+    referrer: Option<Pubkey>,
+    refunded: bool,
+}
+
+struct Harness {
+    svm: LiteSVM,
+    users: Vec<Keypair>,
+    lottery: Pubkey,
+    randomness: Pubkey,
+    prize_mint: Pubkey,
+    tickets: Vec<TicketModel>,
+    // funded and not yet reclaimed, per sponsor
+    sponsorships: HashMap<usize, u64>,
+    paid_in: u128,
+    paid_out: u128,
+    pot_payouts: u32,
+    compute_units: u64,
+}
+
+impl Harness {
+    fn new(input: &Input) -> Self {
+        let mut svm = LiteSVM::new();
+        svm.add_program_from_file(tokenlottery::ID, PROGRAM_SO)
+            .unwrap_or_else(|err| panic!("loading {PROGRAM_SO}, run anchor build first: {err}"));
+        svm.add_program_from_file(METADATA_PROGRAM_ID, METADATA_SO)
+            .unwrap_or_else(|err| panic!("loading {METADATA_SO}: {err}"));
+        svm.warp_to_slot(START_SLOT);
+
+        let users: Vec<Keypair> = (0..USERS).map(|_| Keypair::new()).collect();
+        for user in &users {
+            svm.airdrop(&user.pubkey(), 1_000_000_000_000).unwrap();
+        }
+
+        // every user holds some of the mint used for prizes and token gates
+        let prize_mint = Pubkey::new_unique();
+        let mut mint = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            mint_authority: COption::None,
+            supply: PRIZE_BALANCE * USERS as u64,
+            decimals: 0,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        }
+        .pack_into_slice(&mut mint);
+        set_token_account(&mut svm, prize_mint, mint);
+        for user in &users {
+            let mut account = vec![0; spl_token::state::Account::LEN];
+            spl_token::state::Account {
+                mint: prize_mint,
+                owner: user.pubkey(),
+                amount: PRIZE_BALANCE,
+                state: spl_token::state::AccountState::Initialized,
+                ..Default::default()
+            }
+            .pack_into_slice(&mut account);
+            set_token_account(&mut svm, pda::associated_token_account(&user.pubkey(), &prize_mint), account);
+        }
+
+        let authority = users[AUTHORITY].pubkey();
+        let mut harness = Self {
+            svm,
+            users,
+            lottery: pda::token_lottery(&authority, 0).0,
+            randomness: Pubkey::new_unique(),
+            prize_mint,
+            tickets: Vec::new(),
+            sponsorships: HashMap::new(),
+            paid_in: 0,
+            paid_out: 0,
+            pot_payouts: 0,
+            compute_units: 0,
+        };
+
+        let end = START_SLOT + input.duration as u64;
+        let setup = [
+            instructions::initialize_registry(&authority),
+            instructions::initialize_config(&authority, 0, 0, 0, end, input.price as u64, input.min_tickets as u64),
+        ];
+        assert!(harness.send(AUTHORITY, &setup), "creating the lottery failed");
+        assert!(
+            harness.send(AUTHORITY, &[instructions::initialize_lottery(&authority, 0)]),
+            "creating the collection failed"
+        );
+        harness
+    }
+
+    fn user(&self, index: u8) -> usize {
+        index as usize % USERS
+    }
+
+    fn key(&self, index: usize) -> Pubkey {
+        self.users[index].pubkey()
+    }
+
+    fn slot(&self) -> u64 {
+        self.svm.get_sysvar::<Clock>().slot
+    }
+
+    fn lottery(&self) -> Option<TokenLottery> {
+        let account = self.svm.get_account(&self.lottery)?;
+        TokenLottery::try_deserialize(&mut &account.data[..]).ok()
+    }
+
+    // Shards that are still open, finalize_shards closes them.
+    fn shards(&self) -> Vec<(Pubkey, TicketShard, u64)> {
+        (0..tokenlottery::MAX_TICKET_SHARDS as u8)
+            .filter_map(|shard| {
+                let address = pda::ticket_shard(&self.lottery, shard).0;
+                let account = self.svm.get_account(&address).filter(|account| account.lamports > 0)?;
+                let ticket_shard = TicketShard::try_deserialize(&mut &account.data[..]).ok()?;
+                Some((address, ticket_shard, account.lamports))
+            })
+            .collect()
+    }
+
+    fn prizes(&self) -> Vec<Prize> {
+        let prize = pda::prize(&self.lottery, &self.prize_mint).0;
+        self.svm
+            .get_account(&prize)
+            .and_then(|account| Prize::try_deserialize(&mut &account.data[..]).ok())
+            .into_iter()
+            .collect()
+    }
+
+    fn lottery_lamports(&self) -> u64 {
+        self.svm.get_balance(&self.lottery).unwrap_or(0)
+    }
+
+    // Sends `instructions` signed by user `signer`, returns whether they succeeded.
+    fn send(&mut self, signer: usize, instructions: &[Instruction]) -> bool {
+        let mut all = vec![ComputeBudgetInstruction::set_compute_unit_limit(1_400_000)];
+        all.extend_from_slice(instructions);
+        let payer = &self.users[signer];
+        // recipients asked to sign co-sign the purchase, as their wallet would
+        let cosigners = self.users.iter().filter(|user| {
+            user.pubkey() != payer.pubkey()
+                && all.iter().flat_map(|ix| &ix.accounts).any(|meta| meta.is_signer && meta.pubkey == user.pubkey())
+        });
+        let signers: Vec<&Keypair> = std::iter::once(payer).chain(cosigners).collect();
+        let tx = Transaction::new_signed_with_payer(&all, Some(&payer.pubkey()), &signers, self.svm.latest_blockhash());
+        let result = self.svm.send_transaction(tx);
+        // identical transactions in a row would otherwise be rejected as duplicates
+        self.svm.expire_blockhash();
+
+        let (ok, meta) = match result {
+            Ok(meta) => (true, meta),
+            Err(failed) => (false, failed.meta),
+        };
+        self.compute_units = meta.compute_units_consumed;
+        let logs = meta.logs;
+        assert!(!logs.iter().any(|line| line.contains("panicked")), "program panicked: {logs:#?}");
+        ok
+    }
+
+    fn set_randomness(&mut self, seed_slot: u64, reveal_slot: u64, value: [u8; 32], owner: Pubkey) {
+        let mut randomness: RandomnessAccountData = bytemuck::Zeroable::zeroed();
+        randomness.seed_slot = seed_slot;
+        randomness.reveal_slot = reveal_slot;
+        randomness.value = value;
+        let mut data = RandomnessAccountData::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(&randomness));
+        let account = Account {
+            lamports: self.svm.minimum_balance_for_rent_exemption(data.len()),
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        };
+        self.svm.set_account(self.randomness, account).unwrap();
+    }
+
+    fn record_ticket(&mut self, mint: Pubkey) {
+        assert!(
+            self.compute_units <= MINT_TICKET_COMPUTE_UNITS,
+            "minting a ticket used {} compute units, over the {MINT_TICKET_COMPUTE_UNITS} budget",
+            self.compute_units
+        );
+        let account = self.svm.get_account(&pda::ticket(&mint).0).expect("bought ticket has no receipt");
+        let ticket = Ticket::try_deserialize(&mut &account.data[..]).unwrap();
+        self.paid_in += ticket.principal() as u128;
+        let referrer = (ticket.referral_fee > 0).then_some(ticket.referrer);
+        self.tickets.push(TicketModel { mint, principal: ticket.principal(), referrer, refunded: false });
+    }
+
+    fn refund(&mut self, ticket: usize) {
+        let ticket = &mut self.tickets[ticket];
+        assert!(!ticket.refunded, "ticket {} refunded twice", ticket.mint);
+        ticket.refunded = true;
+        self.paid_out += ticket.principal as u128;
+    }
+
+    fn ticket(&self, index: u8) -> Option<usize> {
+        (!self.tickets.is_empty()).then(|| index as usize % self.tickets.len())
+    }
+
+    fn run(&mut self, action: &Action) {
+        let Some(lottery) = self.lottery() else {
+            // closed, every instruction fails from here on
+            return;
+        };
+        let token_lottery = self.lottery;
+
+        match *action {
+            Action::Warp { slots } => {
+                let slot = self.slot() + slots as u64;
+                self.svm.warp_to_slot(slot);
+            }
+            Action::MigrateLottery { signer } => {
+                let signer = self.user(signer);
+                self.send(signer, &[instructions::migrate_lottery(&self.key(signer), &token_lottery)]);
+            }
+            Action::ConfigurePricing { signer, ref tiers, curve, param } => {
+                let signer = self.user(signer);
+                let price_tiers = tiers
+                    .iter()
+                    .take(6)
+                    .map(|(until, price)| PriceTier { until: START_SLOT + *until as u64, price: *price as u64 })
+                    .collect();
+                let bonding_curve = match curve % 3 {
+                    0 => BondingCurve::Flat,
+                    1 => BondingCurve::Linear { step: param as u64 },
+                    _ => BondingCurve::Exponential { growth_bps: param },
+                };
+                let ix = instructions::configure_pricing(&self.key(signer), &token_lottery, price_tiers, bonding_curve);
+                self.send(signer, &[ix]);
+            }
+            Action::BuyTicket { signer, recipient, slack, referrer, recipient_signs } => {
+                let (signer, recipient) = (self.user(signer), self.user(recipient));
+                let mut buy = instructions::BuyTicket::new(&self.key(signer), &lottery).recipient(&self.key(recipient));
+                if recipient_signs {
+                    buy = buy.recipient_signer();
+                }
+                if let Some(referrer) = referrer {
+                    buy = buy.referrer(&self.key(self.user(referrer)));
+                }
+                let max_price = lottery.current_price(self.slot()).unwrap_or(0).saturating_add(slack as u64);
+                let mut ix = buy.instruction(max_price);
+                // the builder always has soulbound recipients sign, a buyer
+                // trying to get a transferable ticket out of one wouldn't
+                let unsigned = !recipient_signs && signer != recipient;
+                if unsigned {
+                    let recipient = self.key(recipient);
+                    ix.accounts.iter_mut().filter(|meta| meta.pubkey == recipient).for_each(|meta| meta.is_signer = false);
+                }
+                if self.send(signer, &[ix]) {
+                    assert!(
+                        !(unsigned && lottery.ticket_freeze == TicketFreeze::Soulbound),
+                        "soulbound ticket minted to a recipient that didn't sign"
+                    );
+                    self.record_ticket(buy.ticket_mint());
+                }
+            }
+            Action::BuyTicketAllowlisted { signer, recipient, slack, max_allowance } => {
+                let (signer, recipient) = (self.user(signer), self.user(recipient));
+                let buy = instructions::BuyTicket::new(&self.key(signer), &lottery).recipient(&self.key(recipient));
+                let max_price = lottery.current_price(self.slot()).unwrap_or(0).saturating_add(slack as u64);
+                // single leaf allowlists have an empty proof
+                if self.send(signer, &[buy.allowlisted(max_price, max_allowance as u64, vec![])]) {
+                    self.record_ticket(buy.ticket_mint());
+                }
+            }
+            Action::IssueTicket { recipient } => {
+                let recipient = self.user(recipient);
+                let (ix, mint) = instructions::issue_ticket(&self.key(recipient), &lottery);
+                if self.send(AUTHORITY, &[ix]) {
+                    self.record_ticket(mint);
+                }
+            }
+            Action::ConfigurePromo { signer, max_promo_tickets } => {
+                let signer = self.user(signer);
+                let ix = instructions::configure_promo(&self.key(signer), &token_lottery, max_promo_tickets.map(u64::from));
+                self.send(signer, &[ix]);
+            }
+            Action::InitializeEntryWeights { signer } => {
+                let signer = self.user(signer);
+                self.send(signer, &[instructions::initialize_entry_weights(&self.key(signer), &token_lottery)]);
+            }
+            Action::SetEntryWeight { signer, index, weight } => {
+                let signer = self.user(signer);
+                let ix = instructions::set_entry_weight(&self.key(signer), &token_lottery, index as u64, weight as u64);
+                self.send(signer, &[ix]);
+            }
+            Action::ConfigureGate { signer, kind, holder } => {
+                let signer = self.user(signer);
+                let purchase_gate = match kind % 3 {
+                    0 => PurchaseGate::None,
+                    1 => PurchaseGate::TokenBalance { mint: self.prize_mint, min_amount: 1 },
+                    _ => PurchaseGate::Collection { collection_mint: self.key(self.user(holder)) },
+                };
+                self.send(signer, &[instructions::configure_gate(&self.key(signer), &token_lottery, purchase_gate)]);
+            }
+            Action::ConfigureReferrals { signer, referral_bps } => {
+                let signer = self.user(signer);
+                let ix = instructions::configure_referrals(&self.key(signer), &token_lottery, referral_bps);
+                self.send(signer, &[ix]);
+            }
+            Action::RegisterReferrer { signer } => {
+                let signer = self.user(signer);
+                self.send(signer, &[instructions::register_referrer(&self.key(signer), &token_lottery)]);
+            }
+            Action::ClaimReferralRewards { signer } => {
+                let signer = self.user(signer);
+                self.send(signer, &[instructions::claim_referral_rewards(&self.key(signer), &token_lottery)]);
+            }
+            Action::ConfigureAllowlist { signer, allowed, max_allowance, presale_len } => {
+                let signer = self.user(signer);
+                let leaf = keccak::hashv(&[self.key(self.user(allowed)).as_ref(), &(max_allowance as u64).to_le_bytes()]);
+                let start = self.slot();
+                let ix = instructions::configure_allowlist(
+                    &self.key(signer),
+                    &token_lottery,
+                    leaf.to_bytes(),
+                    start,
+                    start + presale_len as u64,
+                );
+                self.send(signer, &[ix]);
+            }
+            Action::CommitAWinner { signer } => {
+                let signer = self.user(signer);
+                let slot = self.slot();
+                self.set_randomness(slot - 1, 0, [0; 32], SWITCHBOARD_PROGRAM_ID);
+                let committed = lottery.randomness_account != Pubkey::default();
+                let ix = instructions::commit_a_winner(&self.key(signer), &token_lottery, &self.randomness);
+                assert!(!(self.send(signer, &[ix]) && committed), "committed a lottery to randomness twice");
+            }
+            Action::ChooseAWinner { signer, value } => {
+                let signer = self.user(signer);
+                let slot = self.slot();
+                self.set_randomness(slot - 1, slot, value, SWITCHBOARD_PROGRAM_ID);
+                self.send(signer, &[instructions::choose_a_winner(&self.key(signer), &lottery)]);
+            }
+            Action::ForgeRandomness { signer, value, draw } => {
+                // well-formed randomness data under any other owner is chosen by its creator
+                let signer = self.user(signer);
+                let slot = self.slot();
+                let (reveal_slot, ix) = if draw {
+                    (slot, instructions::choose_a_winner(&self.key(signer), &lottery))
+                } else {
+                    (0, instructions::commit_a_winner(&self.key(signer), &token_lottery, &self.randomness))
+                };
+                self.set_randomness(slot - 1, reveal_slot, value, Pubkey::new_unique());
+                assert!(!self.send(signer, &[ix]), "accepted a randomness account not owned by Switchboard");
+            }
+            Action::CloseLottery { signer } => {
+                let signer = self.user(signer);
+                self.send(signer, &[instructions::close_lottery(&self.key(signer), &token_lottery)]);
+            }
+            Action::ClaimPrize { signer, ticket } => {
+                let (signer, Some(ticket)) = (self.user(signer), self.ticket(ticket)) else { return };
+                let before = self.lottery_lamports();
+                let ix = instructions::claim_prize(&self.key(signer), &lottery, &self.tickets[ticket].mint, &self.prizes());
+                if self.send(signer, &[ix]) {
+                    let payout = before.saturating_sub(self.lottery_lamports());
+                    if payout > 0 {
+                        self.pot_payouts += 1;
+                        self.paid_out += payout as u128;
+                    }
+                }
+            }
+            Action::ClaimPrizeFor { signer, ticket, holder, recipient } => {
+                let (signer, holder, Some(ticket)) = (self.user(signer), self.user(holder), self.ticket(ticket)) else {
+                    return;
+                };
+                let (holder, mint) = (self.key(holder), self.tickets[ticket].mint);
+                let recipient = recipient.map(|recipient| self.key(self.user(recipient)));
+                let token_account = pda::associated_token_account(&holder, &mint);
+                let ix =
+                    instructions::claim_prize_for(&holder, &token_account, recipient.as_ref(), &lottery, &mint, &self.prizes());
+                let before = self.lottery_lamports();
+                if self.send(signer, &[ix]) {
+                    let payout = before.saturating_sub(self.lottery_lamports());
+                    if payout > 0 {
+                        self.pot_payouts += 1;
+                        self.paid_out += payout as u128;
+                    }
+                }
+            }
+            Action::DesignatePrizeRecipient { signer, ticket, recipient } => {
+                let (signer, Some(ticket)) = (self.user(signer), self.ticket(ticket)) else { return };
+                let (holder, mint) = (self.key(signer), self.tickets[ticket].mint);
+                let token_account = pda::associated_token_account(&holder, &mint);
+                let recipient = self.key(self.user(recipient));
+                self.send(signer, &[instructions::designate_prize_recipient(&holder, &token_account, &mint, &recipient)]);
+            }
+            Action::FundPot { signer, amount } => {
+                let signer = self.user(signer);
+                if self.send(signer, &[instructions::fund_pot(&self.key(signer), &token_lottery, amount as u64)]) {
+                    self.paid_in += amount as u128;
+                    *self.sponsorships.entry(signer).or_default() += amount as u64;
+                }
+            }
+            Action::CancelLottery { signer } => {
+                let signer = self.user(signer);
+                let ix = instructions::cancel_lottery(&self.key(signer), &token_lottery, &self.prizes());
+                self.send(signer, &[ix]);
+            }
+            Action::DepositPrize { signer, amount } => {
+                let signer = self.user(signer);
+                let depositor = self.key(signer);
+                let source = pda::associated_token_account(&depositor, &self.prize_mint);
+                let ix = instructions::deposit_prize(&depositor, &token_lottery, &self.prize_mint, &source, amount as u64);
+                self.send(signer, &[ix]);
+            }
+            Action::ApprovePrizeDepositor { signer, depositor } => {
+                let (signer, depositor) = (self.user(signer), self.user(depositor));
+                let ix = instructions::approve_prize_depositor(&self.key(signer), &token_lottery, &self.key(depositor));
+                self.send(signer, &[ix]);
+            }
+            Action::RevokePrizeDepositor { signer, depositor } => {
+                let (signer, depositor) = (self.user(signer), self.user(depositor));
+                let ix = instructions::revoke_prize_depositor(&self.key(signer), &token_lottery, &self.key(depositor));
+                self.send(signer, &[ix]);
+            }
+            Action::ClaimRefund { signer, ticket } => {
+                let (signer, Some(ticket)) = (self.user(signer), self.ticket(ticket)) else { return };
+                let model = &self.tickets[ticket];
+                let ix = instructions::claim_refund(&self.key(signer), &lottery, &model.mint, model.referrer.as_ref());
+                if self.send(signer, &[ix]) {
+                    self.refund(ticket);
+                }
+            }
+            Action::WithdrawPrincipal { signer, ticket } => {
+                let (signer, Some(ticket)) = (self.user(signer), self.ticket(ticket)) else { return };
+                let ix = instructions::withdraw_principal(&self.key(signer), &lottery, &self.tickets[ticket].mint);
+                if self.send(signer, &[ix]) {
+                    self.refund(ticket);
+                }
+            }
+            Action::ConfigureNoLoss { signer, is_no_loss } => {
+                let signer = self.user(signer);
+                self.send(signer, &[instructions::configure_no_loss(&self.key(signer), &token_lottery, is_no_loss)]);
+            }
+            Action::InitializeTicketShard { signer, shard } => {
+                let signer = self.user(signer);
+                let ix = instructions::initialize_ticket_shard(&self.key(signer), &token_lottery, shard % 10);
+                self.send(signer, &[ix]);
+            }
+            Action::BuyTicketSharded { signer, recipient, slack, shard, referrer } => {
+                let (signer, recipient) = (self.user(signer), self.user(recipient));
+                let shards = self.shards();
+                let Some((_, ticket_shard, _)) = shards.get(shard as usize % shards.len().max(1)) else { return };
+                let mut buy = instructions::BuyTicket::new(&self.key(signer), &lottery)
+                    .recipient(&self.key(recipient))
+                    .shard(ticket_shard);
+                if let Some(referrer) = referrer {
+                    buy = buy.referrer(&self.key(self.user(referrer)));
+                }
+                let max_price = lottery.current_price(self.slot()).unwrap_or(0).saturating_add(slack as u64);
+                if self.send(signer, &[buy.instruction(max_price)]) {
+                    self.record_ticket(buy.ticket_mint());
+                }
+            }
+            Action::FinalizeShards => {
+                self.send(AUTHORITY, &[instructions::finalize_shards(&lottery)]);
+            }
+            Action::BurnTicket { signer, ticket } => {
+                let (signer, Some(ticket)) = (self.user(signer), self.ticket(ticket)) else { return };
+                let before = self.lottery_lamports();
+                let ix = instructions::burn_ticket(&self.key(signer), &lottery, &self.tickets[ticket].mint);
+                if self.send(signer, &[ix]) {
+                    assert_eq!(self.lottery_lamports(), before, "burning a ticket moved the pot");
+                }
+            }
+            Action::ConfigureTicketFreeze { signer, kind } => {
+                let signer = self.user(signer);
+                let ticket_freeze = match kind % 2 {
+                    0 => TicketFreeze::Transferable,
+                    _ => TicketFreeze::Soulbound,
+                };
+                let ix = instructions::configure_ticket_freeze(&self.key(signer), &token_lottery, ticket_freeze);
+                self.send(signer, &[ix]);
+            }
+            Action::ReclaimSponsorship { signer } => {
+                let signer = self.user(signer);
+                if self.send(signer, &[instructions::reclaim_sponsorship(&self.key(signer), &token_lottery)]) {
+                    self.paid_out += self.sponsorships.remove(&signer).unwrap_or(0) as u128;
+                }
+            }
+        }
+    }
+
+    fn check_invariants(&self) {
+        assert!(self.pot_payouts <= 1, "pot paid out {} times", self.pot_payouts);
+
+        let Some(account) = self.svm.get_account(&self.lottery).filter(|account| account.lamports > 0) else {
+            return;
+        };
+        let lottery = TokenLottery::try_deserialize(&mut &account.data[..]).unwrap();
+        let rent = self.svm.minimum_balance_for_rent_exemption(account.data.len());
+        assert_eq!(
+            account.lamports.checked_sub(rent),
+            Some(lottery.lottery_pot_amount),
+            "pot is not backed by the lottery's lamports"
+        );
+
+        let mut pot = lottery.lottery_pot_amount as u128;
+        let shard_rent = self.svm.minimum_balance_for_rent_exemption(8 + TicketShard::INIT_SPACE);
+        for (address, ticket_shard, lamports) in self.shards() {
+            assert_eq!(
+                lamports.checked_sub(shard_rent),
+                Some(ticket_shard.pot_amount),
+                "pot of shard {address} is not backed by its lamports"
+            );
+            pot += ticket_shard.pot_amount as u128;
+        }
+        assert_eq!(pot, self.paid_in - self.paid_out, "pots differ from payments minus payouts");
+    }
+}
+
+fn set_token_account(svm: &mut LiteSVM, address: Pubkey, data: Vec<u8>) {
+    let account = Account {
+        lamports: svm.minimum_balance_for_rent_exemption(data.len()),
+        data,
+        owner: TOKEN_PROGRAM_ID,
+        executable: false,
+        rent_epoch: 0,
+    };
+    svm.set_account(address, account).unwrap();
+}
+
+fuzz_target!(|input: Input| {
+    let mut harness = Harness::new(&input);
+    harness.check_invariants();
+    for action in input.actions.iter().take(64) {
+        harness.run(action);
+        harness.check_invariants();
+    }
+});
@@ -0,0 +1,14 @@
+#!/usr/bin/env sh
+# Builds the program the harness loads, dumps the Metaplex token metadata
+# program on first use and runs the fuzzer. Extra arguments are passed on to
+# `cargo fuzz run`.
+set -e
+cd "$(dirname "$0")/.."
+
+anchor build
+if [ ! -f fuzz/fixtures/mpl_token_metadata.so ]; then
+  mkdir -p fuzz/fixtures
+  solana program dump -um metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s fuzz/fixtures/mpl_token_metadata.so
+fi
+
+cargo +nightly fuzz run instruction_sequence "$@"
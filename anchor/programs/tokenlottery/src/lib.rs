@@ -1,5 +1,8 @@
 use anchor_lang::prelude::*;
 use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::program_option::COption;
 use switchboard_on_demand::accounts::RandomnessAccountData;
 use anchor_spl::{
   metadata::{
@@ -9,10 +12,16 @@ use anchor_spl::{
     CreateMasterEditionV3,
     SignMetadata,
     SetAndVerifySizedCollectionItem,
+    BurnNft,
+    FreezeDelegatedAccount,
+    ThawDelegatedAccount,
     create_metadata_accounts_v3,
     create_master_edition_v3,
     sign_metadata,
     set_and_verify_sized_collection_item,
+    burn_nft,
+    freeze_delegated_account,
+    thaw_delegated_account,
     mpl_token_metadata::types::{
       DataV2,
       Creator,
@@ -23,6 +32,12 @@ use anchor_spl::{
     Mint, 
     mint_to,
     MintTo,
+    transfer_checked,
+    TransferChecked,
+    close_account,
+    CloseAccount,
+    approve,
+    Approve,
     TokenInterface, 
     TokenAccount
   },
@@ -41,32 +56,168 @@ pub const URI: &str = "Token Lottery";
 #[constant]
 pub const SYMBOL: &str = "TICKET";
 
+#[constant]
+pub const MAX_PRICE_TIERS: u8 = 4;
+
+#[constant]
+pub const MAX_WEIGHTED_ENTRIES: u64 = 1024;
+
+#[constant]
+pub const DEFAULT_ENTRY_WEIGHT: u64 = 1;
+
+#[constant]
+pub const TOKEN_LOTTERY_VERSION: u8 = 2;
+
+// `DrawResult::algorithm_version` values
+#[constant]
+pub const DRAW_ALGORITHM_UNIFORM: u8 = 1;
+
+#[constant]
+pub const DRAW_ALGORITHM_WEIGHTED: u8 = 2;
+
+#[constant]
+pub const MAX_WINNERS: u8 = 1;
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+pub const MAX_TICKET_SHARDS: usize = 8;
+
+// every shard owns this many ticket indices, shard `n` the ones from
+// `(n + 1) * TICKETS_PER_SHARD`, the lottery's own tickets come below
+#[constant]
+pub const TICKETS_PER_SHARD: u64 = 1_000_000;
+
+// oracles sign reveals against the committed slot hash, which the SlotHashes
+// sysvar keeps for this many slots, past it the randomness can't be revealed
+#[constant]
+pub const REVEAL_WINDOW_SLOTS: u64 = 512;
+
+// Switchboard On-Demand program owning randomness accounts, builds with the
+// `devnet` feature (`pnpm anchor-build`, `pnpm anchor-test`) accept devnet
+// randomness instead of mainnet's
+#[cfg(not(feature = "devnet"))]
+pub const SWITCHBOARD_PROGRAM_ID: Pubkey = switchboard_on_demand::ON_DEMAND_MAINNET_PID;
+
+#[cfg(feature = "devnet")]
+pub const SWITCHBOARD_PROGRAM_ID: Pubkey = switchboard_on_demand::ON_DEMAND_DEVNET_PID;
+
 #[program]
 pub mod tokenlottery {
 
     use super::*;
 
+    pub fn initialize_registry(
+      ctx: Context<InitializeRegistry>,
+    ) -> Result<()> {
+      ctx.accounts.registry.lottery_count = 0;
+      ctx.accounts.registry.bump = ctx.bumps.registry;
+
+      Ok(())
+    }
+
     pub fn initialize_config(
       ctx: Context<InitializeConfig>,
-      id: u64,
       start: u64,
       end: u64,
       price: u64,
+      min_tickets: u64,
     ) -> Result<()> {
+      // ids are handed out by the registry
+      let id = ctx.accounts.registry.lottery_count;
+      ctx.accounts.registry.lottery_count = id.checked_add(1).ok_or(ErrorCode::Overflow)?;
+
+      let authority_index = &mut ctx.accounts.authority_index;
+      authority_index.authority = ctx.accounts.payer.key();
+      authority_index.bump = ctx.bumps.authority_index;
+
+      let authority_lottery = &mut ctx.accounts.authority_lottery;
+      authority_lottery.authority = ctx.accounts.payer.key();
+      authority_lottery.index = authority_index.lottery_count;
+      authority_lottery.id = id;
+      authority_lottery.lottery = ctx.accounts.token_lottery.key();
+      authority_lottery.bump = ctx.bumps.authority_lottery;
+
+      authority_index.lottery_count = authority_index.lottery_count.checked_add(1).ok_or(ErrorCode::Overflow)?;
+
+      ctx.accounts.token_lottery.version = TOKEN_LOTTERY_VERSION;
       ctx.accounts.token_lottery.id = id;
       ctx.accounts.token_lottery.bump = ctx.bumps.token_lottery;
       ctx.accounts.token_lottery.lottery_start = start;
       ctx.accounts.token_lottery.lottery_end = end;
       ctx.accounts.token_lottery.price = price;
+      ctx.accounts.token_lottery.min_tickets = min_tickets;
+      ctx.accounts.token_lottery.prize_count = 0;
       ctx.accounts.token_lottery.number_of_tickets = 0;
       ctx.accounts.token_lottery.lottery_pot_amount = 0;
       ctx.accounts.token_lottery.authority = ctx.accounts.payer.key();
       ctx.accounts.token_lottery.randomness_account = Pubkey::default();
       ctx.accounts.token_lottery.is_winner_chosen = false;
+      ctx.accounts.token_lottery.sponsor_amount = 0;
+      ctx.accounts.token_lottery.is_cancelled = false;
+      ctx.accounts.token_lottery.collection_mint_bump = collection_mint_address(&ctx.accounts.payer.key(), id).1;
+
+      emit!(LotteryCreated {
+        lottery: ctx.accounts.token_lottery.key(),
+        id,
+        authority: ctx.accounts.payer.key(),
+        lottery_start: start,
+        lottery_end: end,
+        price,
+        min_tickets,
+      });
       
       Ok(())
     }
 
+    pub fn migrate_lottery(
+      ctx: Context<MigrateLottery>,
+    ) -> Result<()> {
+      let info = ctx.accounts.token_lottery.to_account_info();
+      let token_lottery = TokenLottery::from_v1_data(&info.try_borrow_data()?)?;
+
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+
+      let new_len = 8 + TokenLottery::INIT_SPACE;
+      // the account's lamports include the pot, only the rent for the added
+      // bytes is paid on top
+      let rent = Rent::get()?;
+      let rent = rent.minimum_balance(new_len).checked_sub(rent.minimum_balance(info.data_len())).ok_or(ErrorCode::Overflow)?;
+      if rent > 0 {
+        system_program::transfer(
+          CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+              from: ctx.accounts.payer.to_account_info(),
+              to: info.clone(),
+            }
+          ),
+          rent,
+        )?;
+      }
+
+      info.realloc(new_len, true)?;
+      token_lottery.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+      Ok(())
+    }
+
+    pub fn backfill_ticket(
+      ctx: Context<BackfillTicket>,
+    ) -> Result<()> {
+      let ticket = backfilled_ticket(
+        &ctx.accounts.token_lottery,
+        ctx.accounts.token_lottery.key(),
+        &ctx.accounts.metadata,
+        ctx.accounts.collection_mint.key(),
+        ctx.bumps.ticket,
+      )?;
+      ctx.accounts.ticket.set_inner(ticket);
+
+      Ok(())
+    }
+
     pub fn initialize_lottery(
        ctx: Context<InitializeLottery>,
        id: u64
@@ -83,10 +234,6 @@ pub mod tokenlottery {
       // create metadata
       // create master edition
       // sing_metadata
-      msg!("Collection mint : {:?}", ctx.accounts.collection_mint.key());
-      msg!("Collection mint seeds : {:?}", signer_seeds);
-      msg!("Collection mint bump : {:?}", ctx.bumps.collection_mint);
-      msg!("Mint the collection NFT");
       let cpi_context = CpiContext::new_with_signer(
         ctx.accounts.token_program.to_account_info(),
         MintTo {
@@ -101,8 +248,6 @@ pub mod tokenlottery {
         cpi_context,
         1
       )?;
-      
-      msg!("Creating Metadata accounts");
 
       let cpi_context = CpiContext::new_with_signer(
         ctx.accounts.token_metadata_program.to_account_info(),
@@ -137,8 +282,6 @@ pub mod tokenlottery {
         Some(CollectionDetails::V1 { size: 0 }),
       )?;
 
-      msg!("Creating Master Edition accounts");
-
       let cpi_context = CpiContext::new_with_signer(
         ctx.accounts.token_metadata_program.to_account_info(),
         CreateMasterEditionV3 {
@@ -159,9 +302,7 @@ pub mod tokenlottery {
         cpi_context,
         Some(0)
       )?;
-      
 
-      msg!("verifying collection");
 
       let cpi_context = CpiContext::new_with_signer(
         ctx.accounts.token_metadata_program.to_account_info(),
@@ -178,419 +319,2682 @@ pub mod tokenlottery {
 
       Ok(())
     }
+
+    pub fn configure_pricing(
+      ctx: Context<ConfigurePricing>,
+      price_tiers: Vec<PriceTier>,
+      bonding_curve: BondingCurve,
+    ) -> Result<()> {
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      // pricing is frozen once the first ticket is sold
+      require!(token_lottery.is_configurable(), ErrorCode::LotteryAlreadyStarted);
+      require!(price_tiers.len() <= MAX_PRICE_TIERS as usize, ErrorCode::InvalidPricing);
+      require!(price_tiers.windows(2).all(|w| w[0].until < w[1].until), ErrorCode::InvalidPricing);
+
+      token_lottery.price_tiers = price_tiers;
+      token_lottery.bonding_curve = bonding_curve;
+
+      Ok(())
+    }
     
      pub fn buy_ticket(
       ctx: Context<BuyTicket>,
+      max_price: u64,
      ) -> Result<()> {
       let clock = Clock::get()?;
 
+      require!(!ctx.accounts.token_lottery.is_cancelled, ErrorCode::LotteryCancelled);
+
       // check is lottery is still open
       if clock.slot < ctx.accounts.token_lottery.lottery_start || clock.slot > ctx.accounts.token_lottery.lottery_end {
          return Err(ErrorCode::LotteryNotOpen.into());
       }
 
-      let ticket_name = NAME.to_owned() + ctx.accounts.token_lottery.number_of_tickets.to_string().as_str();
+      // only allowlisted buyers can purchase during the presale
+      require!(!ctx.accounts.token_lottery.is_presale(clock.slot), ErrorCode::PresaleOnly);
 
-      // transfer solana
-      system_program::transfer(
-        CpiContext::new(
-          ctx.accounts.system_program.to_account_info(),
-          system_program::Transfer {
-            from: ctx.accounts.payer.to_account_info(),
-            to: ctx.accounts.token_lottery.to_account_info(),
-          }
-        ),
-        ctx.accounts.token_lottery.price,
-      )?;
-
-      ctx.accounts.token_lottery.lottery_pot_amount += ctx.accounts.token_lottery.price;
+      ctx.accounts.purchase(&ctx.bumps, clock.slot, max_price)
+     }
 
-      let pkey = ctx.accounts.payer.key();
-      let signer_seeds: &[&[&[u8]]] = &[&[
-        b"collection_mint".as_ref(),
-        pkey.as_ref(),
-        &ctx.accounts.token_lottery.id.to_le_bytes(),
-        &[ctx.bumps.collection_mint]
-      ]];
+     pub fn buy_ticket_allowlisted(
+      ctx: Context<BuyTicketAllowlisted>,
+      max_price: u64,
+      max_allowance: u64,
+      proof: Vec<[u8; 32]>,
+     ) -> Result<()> {
+      let clock = Clock::get()?;
+      let token_lottery = &ctx.accounts.buy.token_lottery;
 
-      msg!("Number of tickets: {}, token lottery id {}", ctx.accounts.token_lottery.number_of_tickets, ctx.accounts.token_lottery.id);
+      require!(!token_lottery.is_cancelled, ErrorCode::LotteryCancelled);
+      require!(token_lottery.is_presale(clock.slot), ErrorCode::LotteryNotOpen);
+      require!(clock.slot <= token_lottery.lottery_end, ErrorCode::LotteryNotOpen);
 
-      // mint the ticket
-      msg!("Mint the collection NFT");
-      let cpi_context = CpiContext::new_with_signer(
-        ctx.accounts.token_program.to_account_info(),
-        MintTo {
-          mint: ctx.accounts.ticket_mint.to_account_info(),
-          to: ctx.accounts.destination_token_account.to_account_info(),
-          authority: ctx.accounts.collection_mint.to_account_info()
-        },
-        signer_seeds
+      let buyer = ctx.accounts.buy.buyer.key();
+      let leaf = keccak::hashv(&[buyer.as_ref(), &max_allowance.to_le_bytes()]).to_bytes();
+      require!(
+        verify_merkle_proof(&proof, token_lottery.allowlist_root, leaf),
+        ErrorCode::NotAllowlisted
       );
 
-      mint_to(
-        cpi_context,
-        1
-      )?;
+      let allowlist_usage = &mut ctx.accounts.allowlist_usage;
+      require!(allowlist_usage.purchased < max_allowance, ErrorCode::AllowanceExceeded);
+      allowlist_usage.lottery = token_lottery.key();
+      allowlist_usage.buyer = buyer;
+      allowlist_usage.purchased = allowlist_usage.purchased.checked_add(1).ok_or(ErrorCode::Overflow)?;
+      allowlist_usage.bump = ctx.bumps.allowlist_usage;
 
-      msg!("Creating Metadata accounts");
+      ctx.accounts.buy.purchase(&ctx.bumps.buy, clock.slot, max_price)
+     }
 
-      let cpi_context = CpiContext::new_with_signer(
-        ctx.accounts.token_metadata_program.to_account_info(),
-        CreateMetadataAccountsV3 {
-          metadata: ctx.accounts.metadata.to_account_info(),
-          mint: ctx.accounts.ticket_mint.to_account_info(),
-          mint_authority: ctx.accounts.collection_mint.to_account_info(),
-          payer: ctx.accounts.payer.to_account_info(),
-          update_authority: ctx.accounts.collection_mint.to_account_info(),
-          system_program: ctx.accounts.system_program.to_account_info(),
-          rent: ctx.accounts.rent.to_account_info()
-        },
-        signer_seeds
-      );
-      create_metadata_accounts_v3(
-        cpi_context,
-        DataV2 {
-          name: ticket_name,
-          symbol: SYMBOL.to_string(),
-          uri: URI.to_string(),
-          seller_fee_basis_points: 0,
-          creators: Some(vec![Creator {
-            address: ctx.accounts.collection_mint.key(),
-            verified: false,
-            share: 100
-          }]),
-          collection: None,
-          uses: None,
-        },
-        true,
-        true,
-        None,
-      )?;
+     pub fn issue_ticket(
+      ctx: Context<IssueTicket>,
+     ) -> Result<()> {
+      let clock = Clock::get()?;
+      let token_lottery = &mut ctx.accounts.token_lottery;
 
-      msg!("Creating Master Edition accounts");
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      require!(!token_lottery.is_cancelled, ErrorCode::LotteryCancelled);
+      require!(clock.slot <= token_lottery.lottery_end, ErrorCode::LotteryNotOpen);
+      if let Some(max_promo_tickets) = token_lottery.max_promo_tickets {
+        require!(token_lottery.promo_tickets < max_promo_tickets, ErrorCode::PromoTicketCapReached);
+      }
+      require!(token_lottery.shard_count == 0 || token_lottery.number_of_tickets < TICKETS_PER_SHARD, ErrorCode::ShardFull);
 
-      let cpi_context = CpiContext::new_with_signer(
-        ctx.accounts.token_metadata_program.to_account_info(),
-        CreateMasterEditionV3 {
-          payer: ctx.accounts.payer.to_account_info(),
-          edition: ctx.accounts.master_edition.to_account_info(),
-          mint: ctx.accounts.ticket_mint.to_account_info(),
-          update_authority: ctx.accounts.collection_mint.to_account_info(),
-          mint_authority: ctx.accounts.collection_mint.to_account_info(),
-          metadata: ctx.accounts.metadata.to_account_info(),
-          token_program: ctx.accounts.token_program.to_account_info(),
-          system_program: ctx.accounts.system_program.to_account_info(),
-          rent: ctx.accounts.rent.to_account_info()
-        },
-        signer_seeds
-      );
+      let ticket_name = NAME.to_owned() + token_lottery.number_of_tickets.to_string().as_str();
 
-      create_master_edition_v3(
-        cpi_context,
-        Some(0)
-      )?;
-      
+      let ticket = &mut ctx.accounts.ticket;
+      ticket.lottery = token_lottery.key();
+      ticket.mint = ctx.accounts.ticket_mint.key();
+      ticket.index = token_lottery.number_of_tickets;
+      ticket.price_paid = 0;
+      ticket.referral_fee = 0;
+      ticket.referrer = Pubkey::default();
+      ticket.bump = ctx.bumps.ticket;
 
-      msg!("verifying");
+      let authority = token_lottery.authority;
+      let collection_mint_bump = token_lottery.cached_collection_mint_bump();
+      let signer_seeds: &[&[&[u8]]] = &[&[
+        b"collection_mint".as_ref(),
+        authority.as_ref(),
+        &token_lottery.id.to_le_bytes(),
+        &[collection_mint_bump]
+      ]];
 
-      let cpi_context = CpiContext::new_with_signer(
-        ctx.accounts.token_metadata_program.to_account_info(),
-        SetAndVerifySizedCollectionItem {
-          metadata: ctx.accounts.metadata.to_account_info(),
+      mint_ticket(
+        TicketMintAccounts {
           payer: ctx.accounts.payer.to_account_info(),
-          collection_authority: ctx.accounts.collection_mint.to_account_info(),
-          collection_master_edition: ctx.accounts.collection_master_edition.to_account_info(),
+          ticket_mint: ctx.accounts.ticket_mint.to_account_info(),
+          destination_token_account: ctx.accounts.destination_token_account.to_account_info(),
+          recipient: ctx.accounts.recipient.to_account_info(),
+          metadata: ctx.accounts.metadata.to_account_info(),
+          master_edition: ctx.accounts.master_edition.to_account_info(),
           collection_metadata: ctx.accounts.collection_metadata.to_account_info(),
+          collection_master_edition: ctx.accounts.collection_master_edition.to_account_info(),
           collection_mint: ctx.accounts.collection_mint.to_account_info(),
-          update_authority: ctx.accounts.collection_mint.to_account_info(),
+          token_program: ctx.accounts.token_program.to_account_info(),
+          system_program: ctx.accounts.system_program.to_account_info(),
+          token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+          rent: ctx.accounts.rent.to_account_info(),
         },
-        signer_seeds
-      );
-
-      set_and_verify_sized_collection_item(
-        cpi_context,
-        None
+        ticket_name,
+        token_lottery.ticket_freeze,
+        signer_seeds,
       )?;
 
-      ctx.accounts.token_lottery.number_of_tickets += 1;
+      record_entry(&ctx.accounts.token_lottery, ctx.accounts.entry_weights.as_ref())?;
+
+      emit!(TicketPurchased {
+        lottery: ctx.accounts.token_lottery.key(),
+        ticket_mint: ctx.accounts.ticket_mint.key(),
+        index: ctx.accounts.ticket.index,
+        buyer: ctx.accounts.payer.key(),
+        recipient: ctx.accounts.recipient.key(),
+        price_paid: 0,
+        referral_fee: 0,
+        slot: clock.slot,
+      });
+
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      token_lottery.number_of_tickets = token_lottery.number_of_tickets.checked_add(1).ok_or(ErrorCode::Overflow)?;
+      token_lottery.promo_tickets = token_lottery.promo_tickets.checked_add(1).ok_or(ErrorCode::Overflow)?;
 
       Ok(())
      }
-     
-     pub fn commit_a_winner(
-      ctx: Context<CommitWinner>,
+
+     pub fn configure_promo(
+      ctx: Context<ConfigurePromo>,
+      max_promo_tickets: Option<u64>,
      ) -> Result<()> {
-      
-      let clock = Clock::get()?;
       let token_lottery = &mut ctx.accounts.token_lottery;
       if ctx.accounts.payer.key() != token_lottery.authority {
         return Err(ErrorCode::NotAuthorized.into());
       }
-      
-      let randomness_data = RandomnessAccountData::parse(
-        ctx.accounts.randomness_account_data.data.borrow()
-      ).unwrap();
-
-      if randomness_data.seed_slot != clock.slot - 1 {
-        return Err(ErrorCode::RandomnessAlreadyRevealed.into());
+      if let Some(max_promo_tickets) = max_promo_tickets {
+        require!(token_lottery.promo_tickets <= max_promo_tickets, ErrorCode::PromoTicketCapReached);
       }
-      
-      token_lottery.randomness_account = ctx.accounts.randomness_account_data.key();
+
+      token_lottery.max_promo_tickets = max_promo_tickets;
 
       Ok(())
      }
 
-     pub fn choose_a_winner(
-      ctx: Context<ChooseWinner>,
+     pub fn initialize_entry_weights(
+      ctx: Context<InitializeEntryWeights>,
      ) -> Result<()> {
-      
-      let clock = Clock::get()?;
       let token_lottery = &mut ctx.accounts.token_lottery;
-
-      if ctx.accounts.randomness_account_data.key() != token_lottery.randomness_account {
-        return Err(ErrorCode::IncorrectRandomnessAccount.into());
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
       }
+      require!(token_lottery.is_configurable(), ErrorCode::LotteryAlreadyStarted);
+
+      let mut entry_weights = ctx.accounts.entry_weights.load_init()?;
+      entry_weights.lottery = token_lottery.key();
+      token_lottery.is_weighted = true;
 
+      Ok(())
+     }
+
+     pub fn set_entry_weight(
+      ctx: Context<SetEntryWeight>,
+      index: u64,
+      weight: u64,
+     ) -> Result<()> {
+      let clock = Clock::get()?;
+      let token_lottery = &ctx.accounts.token_lottery;
       if ctx.accounts.payer.key() != token_lottery.authority {
         return Err(ErrorCode::NotAuthorized.into());
       }
+      // weights are final once sales close, which is also before a draw can be committed
+      require!(clock.slot <= token_lottery.lottery_end, ErrorCode::LotteryNotOpen);
+      require!(!token_lottery.is_winner_chosen, ErrorCode::WinnerChosen);
 
-      if clock.slot < token_lottery.lottery_end {
-        msg!("Current Slot: {}", clock.slot);
-        msg!("Lottery End Slot: {}", token_lottery.lottery_end);
-        return Err(ErrorCode::LotteryNotOpen.into());
-      }
+      let mut entry_weights = ctx.accounts.entry_weights.load_mut()?;
+      require!(index < entry_weights.len, ErrorCode::IncorrectTicket);
+      entry_weights.set(index, weight)
+     }
 
-      require!(token_lottery.is_winner_chosen == false, ErrorCode::WinnerChosen);
-      
-      let randomness_data = RandomnessAccountData::parse(
-        ctx.accounts.randomness_account_data.data.borrow()
-      ).unwrap();
+     pub fn configure_gate(
+      ctx: Context<ConfigureGate>,
+      purchase_gate: PurchaseGate,
+     ) -> Result<()> {
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      require!(token_lottery.is_configurable(), ErrorCode::LotteryAlreadyStarted);
 
-      let revealed_random_value = randomness_data.get_value(&clock).map_err(|_| ErrorCode::RandomnessNotResolved)?;
+      token_lottery.purchase_gate = purchase_gate;
 
-      msg!("Random Value: {}", revealed_random_value[0]);
-      msg!("Number of Tickets: {}", token_lottery.number_of_tickets);
+      Ok(())
+     }
 
-      let randomness_results = revealed_random_value[0] as u64 % token_lottery.number_of_tickets;
+     pub fn configure_ticket_freeze(
+      ctx: Context<ConfigureTicketFreeze>,
+      ticket_freeze: TicketFreeze,
+     ) -> Result<()> {
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      require!(token_lottery.is_configurable(), ErrorCode::LotteryAlreadyStarted);
 
-      msg!("Winner: {}", randomness_results);
+      token_lottery.ticket_freeze = ticket_freeze;
 
-      token_lottery.winner = randomness_results;
-      token_lottery.is_winner_chosen = true;                                         
       Ok(())
      }
-     
-     pub fn claim_prize(
-       ctx: Context<ClaimPrize>,
+
+     pub fn configure_referrals(
+      ctx: Context<ConfigureReferrals>,
+      referral_bps: u16,
      ) -> Result<()> {
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      require!(token_lottery.is_configurable(), ErrorCode::LotteryAlreadyStarted);
+      require!(referral_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidReferralShare);
 
-      msg!("Winner chosen: {}", ctx.accounts.token_lottery.is_winner_chosen);
-      require!(ctx.accounts.token_lottery.is_winner_chosen, ErrorCode::WinnerNotChosen);
-      
-      // Check if token is a part of the collection
-      require!(ctx.accounts.metadata.collection.as_ref().unwrap().verified, ErrorCode::NotVerifiedTicket);
-      require!(ctx.accounts.metadata.collection.as_ref().unwrap().key == ctx.accounts.collection_mint.key(), ErrorCode::IncorrectTicket);
-      
-      let ticket_name = NAME.to_owned() + &ctx.accounts.token_lottery.winner.to_string();
-      let metadata_name = ctx.accounts.metadata.name.replace("\u{0}", "");
-      
-      msg!("Ticket name: {}", ticket_name);
-      msg!("Metdata name: {}", metadata_name);
+      token_lottery.referral_bps = referral_bps;
 
-      // Check if the winner has the winning ticket
-      require!(metadata_name == ticket_name, ErrorCode::IncorrectTicket);
-      require!(ctx.accounts.destination_token_account.amount > 0, ErrorCode::IncorrectTicket);
-     
-      **ctx.accounts.token_lottery.to_account_info().try_borrow_mut_lamports()? -= ctx.accounts.token_lottery.lottery_pot_amount;
-      **ctx.accounts.payer.try_borrow_mut_lamports()? += ctx.accounts.token_lottery.lottery_pot_amount;
-      
-      ctx.accounts.token_lottery.lottery_pot_amount = 0;
+      token_lottery.check_referrals()
+     }
+
+     pub fn register_referrer(
+      ctx: Context<RegisterReferrer>,
+     ) -> Result<()> {
+      let referral = &mut ctx.accounts.referral;
+      referral.lottery = ctx.accounts.token_lottery.key();
+      referral.referrer = ctx.accounts.referrer.key();
+      referral.accrued = 0;
+      referral.bump = ctx.bumps.referral;
 
       Ok(())
-     
      }
 
+     pub fn claim_referral_rewards(
+      ctx: Context<ClaimReferralRewards>,
+     ) -> Result<()> {
+      // refunds pay the fees back to the buyers of lotteries that don't draw
+      require!(ctx.accounts.token_lottery.is_winner_chosen, ErrorCode::WinnerNotChosen);
 
-}
-
+      let amount = ctx.accounts.referral.accrued;
+      ctx.accounts.referral.accrued = 0;
 
+      move_lamports(&ctx.accounts.referral.to_account_info(), &ctx.accounts.referrer.to_account_info(), amount)?;
 
-#[derive(Accounts)]
-#[instruction(id: u64)]
-pub struct InitializeConfig<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
+      Ok(())
+     }
 
-    #[account(
+     pub fn configure_allowlist(
+      ctx: Context<ConfigureAllowlist>,
+      allowlist_root: [u8; 32],
+      presale_start: u64,
+      presale_end: u64,
+     ) -> Result<()> {
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      require!(token_lottery.is_configurable(), ErrorCode::LotteryAlreadyStarted);
+      require!(presale_start <= presale_end, ErrorCode::InvalidPresaleWindow);
+      require!(presale_end <= token_lottery.lottery_end, ErrorCode::InvalidPresaleWindow);
+
+      token_lottery.allowlist_root = allowlist_root;
+      token_lottery.presale_start = presale_start;
+      token_lottery.presale_end = presale_end;
+
+      Ok(())
+     }
+     
+     pub fn commit_a_winner(
+      ctx: Context<CommitWinner>,
+     ) -> Result<()> {
+      
+      let clock = Clock::get()?;
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      // the draw is committed to once sales, and with them the entries, are closed
+      require!(clock.slot > token_lottery.lottery_end, ErrorCode::LotteryNotOpen);
+      // only `recommit_a_winner` replaces a commit, once it can't be revealed
+      require!(token_lottery.randomness_account == Pubkey::default(), ErrorCode::RandomnessCommitted);
+      
+      let randomness_data = RandomnessAccountData::parse(
+        ctx.accounts.randomness_account_data.data.borrow()
+      ).map_err(|_| ErrorCode::InvalidRandomnessAccount)?;
+
+      if Some(randomness_data.seed_slot) != clock.slot.checked_sub(1) {
+        return Err(ErrorCode::RandomnessAlreadyRevealed.into());
+      }
+      
+      token_lottery.randomness_account = ctx.accounts.randomness_account_data.key();
+
+      Ok(())
+     }
+
+     // Replaces committed randomness that was never revealed and no longer
+     // can be, so a missed reveal doesn't lock the lottery for good.
+     pub fn recommit_a_winner(
+      ctx: Context<RecommitWinner>,
+     ) -> Result<()> {
+
+      let clock = Clock::get()?;
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      require!(!token_lottery.is_winner_chosen, ErrorCode::WinnerChosen);
+      require!(!token_lottery.is_cancelled, ErrorCode::LotteryCancelled);
+      if ctx.accounts.committed_randomness_account_data.key() != token_lottery.randomness_account {
+        return Err(ErrorCode::IncorrectRandomnessAccount.into());
+      }
+
+      let committed_data = RandomnessAccountData::parse(
+        ctx.accounts.committed_randomness_account_data.data.borrow()
+      ).map_err(|_| ErrorCode::InvalidRandomnessAccount)?;
+      require!(is_randomness_expired(&committed_data, clock.slot), ErrorCode::RandomnessNotExpired);
+
+      let randomness_data = RandomnessAccountData::parse(
+        ctx.accounts.randomness_account_data.data.borrow()
+      ).map_err(|_| ErrorCode::InvalidRandomnessAccount)?;
+
+      if Some(randomness_data.seed_slot) != clock.slot.checked_sub(1) {
+        return Err(ErrorCode::RandomnessAlreadyRevealed.into());
+      }
+
+      token_lottery.randomness_account = ctx.accounts.randomness_account_data.key();
+
+      Ok(())
+     }
+
+     pub fn choose_a_winner(
+      ctx: Context<ChooseWinner>,
+     ) -> Result<()> {
+      
+      let clock = Clock::get()?;
+      let token_lottery = &mut ctx.accounts.token_lottery;
+
+      if ctx.accounts.randomness_account_data.key() != token_lottery.randomness_account {
+        return Err(ErrorCode::IncorrectRandomnessAccount.into());
+      }
+
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+
+      if clock.slot <= token_lottery.lottery_end {
+        msg!("Current Slot: {}", clock.slot);
+        msg!("Lottery End Slot: {}", token_lottery.lottery_end);
+        return Err(ErrorCode::LotteryNotOpen.into());
+      }
+
+      require!(!token_lottery.is_winner_chosen, ErrorCode::WinnerChosen);
+      require!(!token_lottery.is_cancelled, ErrorCode::LotteryCancelled);
+      require!(!token_lottery.is_sharding_pending(), ErrorCode::ShardsNotFinalized);
+      require!(token_lottery.number_of_tickets >= token_lottery.min_tickets, ErrorCode::BelowMinimumTickets);
+      
+      let randomness_data = RandomnessAccountData::parse(
+        ctx.accounts.randomness_account_data.data.borrow()
+      ).map_err(|_| ErrorCode::InvalidRandomnessAccount)?;
+
+      let revealed_random_value = randomness_data.get_value(&clock).map_err(|_| ErrorCode::RandomnessNotResolved)?;
+
+      msg!("Random Value: {:?}", &revealed_random_value[..8]);
+      msg!("Number of Tickets: {}", token_lottery.number_of_tickets);
+
+      let algorithm = if token_lottery.is_weighted { DRAW_ALGORITHM_WEIGHTED } else { DRAW_ALGORITHM_UNIFORM };
+      let randomness_results = if token_lottery.is_weighted {
+        let entry_weights = ctx.accounts.entry_weights.as_ref().ok_or(ErrorCode::EntryWeightsRequired)?.load()?;
+        require!(entry_weights.lottery == token_lottery.key(), ErrorCode::EntryWeightsRequired);
+
+        let target = tokenlottery_draw::weighted_target(&revealed_random_value, entry_weights.total_weight)
+          .ok_or(ErrorCode::NoTickets)?;
+        entry_weights.find(target)
+      } else {
+        let ordinal = tokenlottery_draw::uniform_winner(&revealed_random_value, token_lottery.number_of_tickets)
+          .ok_or(ErrorCode::NoTickets)?;
+        token_lottery.ticket_index_at(ordinal).ok_or(ErrorCode::Overflow)?
+      };
+
+      msg!("Winner: {}", randomness_results);
+
+      token_lottery.winner = randomness_results;
+      token_lottery.is_winner_chosen = true;                                         
+
+      let draw_result = &mut ctx.accounts.draw_result;
+      draw_result.lottery = token_lottery.key();
+      draw_result.randomness_account = token_lottery.randomness_account;
+      draw_result.revealed_value = revealed_random_value;
+      draw_result.commit_slot = randomness_data.seed_slot;
+      draw_result.reveal_slot = randomness_data.reveal_slot;
+      draw_result.number_of_tickets = token_lottery.number_of_tickets;
+      draw_result.algorithm_version = algorithm;
+      draw_result.winning_indices = vec![randomness_results];
+      draw_result.bump = ctx.bumps.draw_result;
+
+      emit!(WinnerChosen {
+        lottery: token_lottery.key(),
+        winner: randomness_results,
+        number_of_tickets: token_lottery.number_of_tickets,
+        randomness_account: token_lottery.randomness_account,
+        revealed_value: revealed_random_value,
+        slot: clock.slot,
+      });
+
+      Ok(())
+     }
+
+     pub fn close_lottery(
+       ctx: Context<CloseLottery>,
+     ) -> Result<()> {
+      let clock = Clock::get()?;
+      let token_lottery = &ctx.accounts.token_lottery;
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      // everything owed to winners, holders and depositors has to be paid out first
+      require!(
+        token_lottery.is_winner_chosen || token_lottery.is_refundable(clock.slot),
+        ErrorCode::LotteryNotSettled
+      );
+      require!(!token_lottery.is_sharding_pending(), ErrorCode::ShardsNotFinalized);
+      require!(token_lottery.lottery_pot_amount == 0, ErrorCode::LotteryNotSettled);
+      require!(token_lottery.prize_count == 0, ErrorCode::LotteryNotSettled);
+
+      Ok(())
+     }
+     
+     pub fn claim_prize<'c: 'info, 'info>(
+       ctx: Context<'_, '_, 'c, 'info, ClaimPrize<'info>>,
+     ) -> Result<()> {
+      check_winning_ticket(
+        &ctx.accounts.token_lottery,
+        &ctx.accounts.metadata,
+        &ctx.accounts.collection_mint.key(),
+        &ctx.accounts.destination_token_account,
+      )?;
+
+      // the pot is paid once, claim_prize can be repeated to release escrowed prizes
+      pay_pot(
+        &mut ctx.accounts.token_lottery,
+        &ctx.accounts.ticket,
+        &ctx.accounts.payer.to_account_info(),
+      )?;
+
+      thaw_ticket(
+        &ctx.accounts.token_lottery,
+        &ctx.accounts.destination_token_account,
+        ThawDelegatedAccount {
+          metadata: ctx.accounts.metadata.to_account_info(),
+          delegate: ctx.accounts.collection_mint.to_account_info(),
+          token_account: ctx.accounts.destination_token_account.to_account_info(),
+          edition: ctx.accounts.master_edition.to_account_info(),
+          mint: ctx.accounts.ticket_mint.to_account_info(),
+          token_program: ctx.accounts.token_program.to_account_info(),
+        },
+        ctx.accounts.token_metadata_program.to_account_info(),
+      )?;
+
+      // escrowed prizes are passed as remaining accounts
+      let winner = ctx.accounts.payer.key();
+      release_prizes(
+        &mut ctx.accounts.token_lottery,
+        &ctx.accounts.token_program,
+        ctx.remaining_accounts,
+        Some(winner),
+      )?;
+
+      Ok(())
+     
+     }
+
+     pub fn claim_prize_for<'c: 'info, 'info>(
+       ctx: Context<'_, '_, 'c, 'info, ClaimPrizeFor<'info>>,
+     ) -> Result<()> {
+      check_winning_ticket(
+        &ctx.accounts.token_lottery,
+        &ctx.accounts.metadata,
+        &ctx.accounts.collection_mint.key(),
+        &ctx.accounts.holder_token_account,
+      )?;
+
+      // a designation made by an earlier holder of the ticket doesn't count
+      let holder = ctx.accounts.holder.key();
+      let payee = match PrizeRecipient::designated(&ctx.accounts.prize_recipient)? {
+        Some(prize_recipient) if prize_recipient.holder == holder => prize_recipient.recipient,
+        _ => holder,
+      };
+      require!(ctx.accounts.recipient.key() == payee, ErrorCode::IncorrectRecipient);
+
+      pay_pot(
+        &mut ctx.accounts.token_lottery,
+        &ctx.accounts.ticket,
+        &ctx.accounts.recipient.to_account_info(),
+      )?;
+
+      thaw_ticket(
+        &ctx.accounts.token_lottery,
+        &ctx.accounts.holder_token_account,
+        ThawDelegatedAccount {
+          metadata: ctx.accounts.metadata.to_account_info(),
+          delegate: ctx.accounts.collection_mint.to_account_info(),
+          token_account: ctx.accounts.holder_token_account.to_account_info(),
+          edition: ctx.accounts.master_edition.to_account_info(),
+          mint: ctx.accounts.ticket_mint.to_account_info(),
+          token_program: ctx.accounts.token_program.to_account_info(),
+        },
+        ctx.accounts.token_metadata_program.to_account_info(),
+      )?;
+
+      // escrowed prizes go to the recipient's token accounts
+      release_prizes(
+        &mut ctx.accounts.token_lottery,
+        &ctx.accounts.token_program,
+        ctx.remaining_accounts,
+        Some(payee),
+      )?;
+
+      Ok(())
+     }
+
+     pub fn designate_prize_recipient(
+       ctx: Context<DesignatePrizeRecipient>,
+       recipient: Pubkey,
+     ) -> Result<()> {
+      let prize_recipient = &mut ctx.accounts.prize_recipient;
+      prize_recipient.ticket_mint = ctx.accounts.ticket_mint.key();
+      prize_recipient.holder = ctx.accounts.holder.key();
+      prize_recipient.recipient = recipient;
+      prize_recipient.bump = ctx.bumps.prize_recipient;
+
+      Ok(())
+     }
+
+     pub fn fund_pot(
+       ctx: Context<FundPot>,
+       amount: u64,
+     ) -> Result<()> {
+      let clock = Clock::get()?;
+
+      require!(amount > 0, ErrorCode::InvalidAmount);
+      require!(!ctx.accounts.token_lottery.is_refundable(clock.slot), ErrorCode::LotteryCancelled);
+      require!(!ctx.accounts.token_lottery.is_winner_chosen, ErrorCode::WinnerChosen);
+
+      system_program::transfer(
+        CpiContext::new(
+          ctx.accounts.system_program.to_account_info(),
+          system_program::Transfer {
+            from: ctx.accounts.sponsor.to_account_info(),
+            to: ctx.accounts.token_lottery.to_account_info(),
+          }
+        ),
+        amount,
+      )?;
+
+      let sponsorship = &mut ctx.accounts.sponsorship;
+      sponsorship.lottery = ctx.accounts.token_lottery.key();
+      sponsorship.sponsor = ctx.accounts.sponsor.key();
+      sponsorship.bump = ctx.bumps.sponsorship;
+      sponsorship.amount = sponsorship.amount.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      token_lottery.sponsor_amount = token_lottery.sponsor_amount.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+      token_lottery.lottery_pot_amount = token_lottery.lottery_pot_amount.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+
+      Ok(())
+     }
+
+     pub fn cancel_lottery<'c: 'info, 'info>(
+       ctx: Context<'_, '_, 'c, 'info, CancelLottery<'info>>,
+     ) -> Result<()> {
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      require!(!token_lottery.is_winner_chosen, ErrorCode::WinnerChosen);
+
+      if !token_lottery.is_cancelled {
+        // once the draw is committed, or sales closed with enough tickets,
+        // the lottery has to run to the end
+        let clock = Clock::get()?;
+        require!(token_lottery.randomness_account == Pubkey::default(), ErrorCode::RandomnessCommitted);
+        require!(
+          clock.slot <= token_lottery.lottery_end || token_lottery.is_refundable(clock.slot),
+          ErrorCode::LotteryNotOpen
+        );
+
+        token_lottery.is_cancelled = true;
+        emit!(LotteryCancelled {
+          lottery: token_lottery.key(),
+        });
+      }
+
+      // return escrowed prizes, can be repeated to settle them in batches
+      release_prizes(
+        &mut ctx.accounts.token_lottery,
+        &ctx.accounts.token_program,
+        ctx.remaining_accounts,
+        None,
+      )?;
+
+      Ok(())
+     }
+
+     pub fn deposit_prize(
+       ctx: Context<DepositPrize>,
+       amount: u64,
+     ) -> Result<()> {
+      let clock = Clock::get()?;
+
+      require!(amount > 0, ErrorCode::InvalidAmount);
+      if ctx.accounts.depositor.key() != ctx.accounts.token_lottery.authority
+        && !PrizeDepositor::is_approved(&ctx.accounts.prize_depositor)
+      {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      require!(!ctx.accounts.token_lottery.is_cancelled, ErrorCode::LotteryCancelled);
+      // prizes have to be escrowed before sales open
+      require!(clock.slot < ctx.accounts.token_lottery.lottery_start, ErrorCode::LotteryAlreadyStarted);
+
+      transfer_checked(
+        CpiContext::new(
+          ctx.accounts.token_program.to_account_info(),
+          TransferChecked {
+            from: ctx.accounts.depositor_token_account.to_account_info(),
+            mint: ctx.accounts.prize_mint.to_account_info(),
+            to: ctx.accounts.prize_vault.to_account_info(),
+            authority: ctx.accounts.depositor.to_account_info(),
+          }
+        ),
+        amount,
+        ctx.accounts.prize_mint.decimals,
+      )?;
+
+      let prize = &mut ctx.accounts.prize;
+      // further deposits of the same mint top up the prize
+      if prize.lottery != Pubkey::default() {
+        require!(prize.depositor == ctx.accounts.depositor.key(), ErrorCode::IncorrectDepositor);
+        prize.amount = prize.amount.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        return Ok(());
+      }
+
+      prize.lottery = ctx.accounts.token_lottery.key();
+      prize.mint = ctx.accounts.prize_mint.key();
+      prize.vault = ctx.accounts.prize_vault.key();
+      prize.depositor = ctx.accounts.depositor.key();
+      prize.amount = amount;
+      prize.bump = ctx.bumps.prize;
+
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      token_lottery.prize_count = token_lottery.prize_count.checked_add(1).ok_or(ErrorCode::Overflow)?;
+
+      Ok(())
+     }
+
+     pub fn approve_prize_depositor(
+       ctx: Context<ApprovePrizeDepositor>,
+       depositor: Pubkey,
+     ) -> Result<()> {
+      if ctx.accounts.payer.key() != ctx.accounts.token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+
+      let prize_depositor = &mut ctx.accounts.prize_depositor;
+      prize_depositor.lottery = ctx.accounts.token_lottery.key();
+      prize_depositor.depositor = depositor;
+      prize_depositor.bump = ctx.bumps.prize_depositor;
+
+      Ok(())
+     }
+
+     pub fn revoke_prize_depositor(
+       ctx: Context<RevokePrizeDepositor>,
+     ) -> Result<()> {
+      if ctx.accounts.payer.key() != ctx.accounts.token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+
+      Ok(())
+     }
+
+     pub fn claim_refund(
+       ctx: Context<ClaimRefund>,
+     ) -> Result<()> {
+      let clock = Clock::get()?;
+      require!(ctx.accounts.token_lottery.is_refundable(clock.slot), ErrorCode::LotteryNotRefundable);
+
+      // Check if token is a part of the collection
+      verify_collection_item(&ctx.accounts.metadata, ctx.accounts.collection_mint.key())?;
+
+      ctx.accounts.burn_and_refund(true)
+     }
+
+     pub fn withdraw_principal(
+       ctx: Context<ClaimRefund>,
+     ) -> Result<()> {
+      let token_lottery = &ctx.accounts.token_lottery;
+      require!(token_lottery.is_no_loss, ErrorCode::NotNoLossLottery);
+      require!(token_lottery.is_winner_chosen, ErrorCode::WinnerNotChosen);
+      // the winner gets their principal back with the prize
+      require!(ctx.accounts.ticket.index != token_lottery.winner, ErrorCode::IncorrectTicket);
+
+      // Check if token is a part of the collection
+      verify_collection_item(&ctx.accounts.metadata, ctx.accounts.collection_mint.key())?;
+
+      ctx.accounts.burn_and_refund(false)
+     }
+
+     pub fn configure_no_loss(
+       ctx: Context<ConfigureNoLoss>,
+       is_no_loss: bool,
+     ) -> Result<()> {
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      require!(token_lottery.is_configurable(), ErrorCode::LotteryAlreadyStarted);
+
+      token_lottery.is_no_loss = is_no_loss;
+
+      token_lottery.check_referrals()
+     }
+
+     pub fn reclaim_sponsorship(
+       ctx: Context<ReclaimSponsorship>,
+     ) -> Result<()> {
+      let clock = Clock::get()?;
+      require!(ctx.accounts.token_lottery.is_refundable(clock.slot), ErrorCode::LotteryNotRefundable);
+
+      let amount = ctx.accounts.sponsorship.amount;
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      token_lottery.sponsor_amount = token_lottery.sponsor_amount.checked_sub(amount).ok_or(ErrorCode::Overflow)?;
+      token_lottery.lottery_pot_amount = token_lottery.lottery_pot_amount.checked_sub(amount).ok_or(ErrorCode::Overflow)?;
+
+      move_lamports(&ctx.accounts.token_lottery.to_account_info(), &ctx.accounts.sponsor.to_account_info(), amount)?;
+
+      Ok(())
+     }
+
+     pub fn initialize_ticket_shard(
+       ctx: Context<InitializeTicketShard>,
+       shard: u8,
+     ) -> Result<()> {
+      let clock = Clock::get()?;
+      let token_lottery = &mut ctx.accounts.token_lottery;
+      if ctx.accounts.payer.key() != token_lottery.authority {
+        return Err(ErrorCode::NotAuthorized.into());
+      }
+      require!(!token_lottery.is_cancelled, ErrorCode::LotteryCancelled);
+      require!(clock.slot <= token_lottery.lottery_end, ErrorCode::LotteryNotOpen);
+      // shards are created in order
+      require!(shard == token_lottery.shard_count, ErrorCode::IncorrectShard);
+      require!((shard as usize) < MAX_TICKET_SHARDS, ErrorCode::TooManyShards);
+      // shard sales can't move a bonding curve or weight tree they don't lock
+      require!(matches!(token_lottery.bonding_curve, BondingCurve::Flat), ErrorCode::ShardingUnsupported);
+      require!(!token_lottery.is_weighted, ErrorCode::ShardingUnsupported);
+
+      let ticket_shard = &mut ctx.accounts.ticket_shard;
+      ticket_shard.lottery = token_lottery.key();
+      ticket_shard.shard = shard;
+      ticket_shard.bump = ctx.bumps.ticket_shard;
+
+      token_lottery.shard_count = token_lottery.shard_count.checked_add(1).ok_or(ErrorCode::Overflow)?;
+
+      Ok(())
+     }
+
+     pub fn buy_ticket_sharded(
+      ctx: Context<BuyTicketSharded>,
+      max_price: u64,
+     ) -> Result<()> {
+      let clock = Clock::get()?;
+      let token_lottery = &ctx.accounts.token_lottery;
+
+      require!(!token_lottery.is_cancelled, ErrorCode::LotteryCancelled);
+      if clock.slot < token_lottery.lottery_start || clock.slot > token_lottery.lottery_end {
+        return Err(ErrorCode::LotteryNotOpen.into());
+      }
+      require!(!token_lottery.is_presale(clock.slot), ErrorCode::PresaleOnly);
+
+      ctx.accounts.purchase(&ctx.bumps, clock.slot, max_price)
+     }
+
+     pub fn finalize_shards<'c: 'info, 'info>(
+       ctx: Context<'_, '_, 'c, 'info, FinalizeShards<'info>>,
+     ) -> Result<()> {
+      let clock = Clock::get()?;
+      let token_lottery = &mut ctx.accounts.token_lottery;
+
+      require!(token_lottery.is_sharding_pending(), ErrorCode::ShardsFinalized);
+      // shards keep selling until the lottery closes
+      require!(clock.slot > token_lottery.lottery_end || token_lottery.is_cancelled, ErrorCode::LotteryNotOpen);
+      // every shard is passed as a remaining account, in order
+      require!(ctx.remaining_accounts.len() == token_lottery.shard_count as usize, ErrorCode::IncorrectShard);
+
+      let lottery_key = token_lottery.key();
+      for (index, account) in ctx.remaining_accounts.iter().enumerate() {
+        let ticket_shard = Account::<TicketShard>::try_from(account)?;
+        require!(ticket_shard.lottery == lottery_key, ErrorCode::IncorrectShard);
+        require!(ticket_shard.shard as usize == index, ErrorCode::IncorrectShard);
+
+        token_lottery.number_of_tickets = token_lottery.number_of_tickets.checked_add(ticket_shard.number_of_tickets).ok_or(ErrorCode::Overflow)?;
+        token_lottery.lottery_pot_amount = token_lottery.lottery_pot_amount.checked_add(ticket_shard.pot_amount).ok_or(ErrorCode::Overflow)?;
+        token_lottery.principal_amount = token_lottery.principal_amount.checked_add(ticket_shard.principal_amount).ok_or(ErrorCode::Overflow)?;
+        token_lottery.referral_total = token_lottery.referral_total.checked_add(ticket_shard.referral_total).ok_or(ErrorCode::Overflow)?;
+        token_lottery.shard_tickets[index] = ticket_shard.number_of_tickets;
+
+        // the sub-pot moves to the lottery, the shard's rent back to the authority
+        move_lamports(account, &token_lottery.to_account_info(), ticket_shard.pot_amount)?;
+        ticket_shard.close(ctx.accounts.authority.to_account_info())?;
+      }
+
+      token_lottery.is_shards_finalized = true;
+
+      Ok(())
+     }
+
+     pub fn burn_ticket(
+       ctx: Context<BurnTicket>,
+     ) -> Result<()> {
+      let token_lottery = &ctx.accounts.token_lottery;
+      require!(token_lottery.is_winner_chosen, ErrorCode::WinnerNotChosen);
+      require!(ctx.accounts.ticket.index != token_lottery.winner, ErrorCode::IncorrectTicket);
+      // burning would forfeit the principal, withdraw_principal burns the ticket the same way
+      require!(!token_lottery.is_no_loss, ErrorCode::PrincipalOutstanding);
+
+      // Check if token is a part of the collection
+      verify_collection_item(&ctx.accounts.metadata, ctx.accounts.collection_mint.key())?;
+
+      thaw_ticket(
+        &ctx.accounts.token_lottery,
+        &ctx.accounts.holder_token_account,
+        ThawDelegatedAccount {
+          metadata: ctx.accounts.metadata.to_account_info(),
+          delegate: ctx.accounts.collection_mint.to_account_info(),
+          token_account: ctx.accounts.holder_token_account.to_account_info(),
+          edition: ctx.accounts.master_edition.to_account_info(),
+          mint: ctx.accounts.ticket_mint.to_account_info(),
+          token_program: ctx.accounts.token_program.to_account_info(),
+        },
+        ctx.accounts.token_metadata_program.to_account_info(),
+      )?;
+
+      // closes the token account, metadata and master edition to the holder
+      // and shrinks the sized collection, only the mint can't be closed
+      burn_nft(
+        CpiContext::new(
+          ctx.accounts.token_metadata_program.to_account_info(),
+          BurnNft {
+            metadata: ctx.accounts.metadata.to_account_info(),
+            owner: ctx.accounts.holder.to_account_info(),
+            mint: ctx.accounts.ticket_mint.to_account_info(),
+            token: ctx.accounts.holder_token_account.to_account_info(),
+            edition: ctx.accounts.master_edition.to_account_info(),
+            spl_token: ctx.accounts.token_program.to_account_info(),
+          }
+        ).with_remaining_accounts(vec![ctx.accounts.collection_metadata.to_account_info()]),
+        Some(ctx.accounts.collection_metadata.key()),
+      )?;
+
+      Ok(())
+     }
+
+
+}
+
+
+// Number of remaining accounts describing one escrowed prize:
+// prize, prize vault, prize mint, destination token account, depositor.
+pub const PRIZE_ACCOUNTS_LEN: usize = 5;
+
+// Moves escrowed prizes out of their vaults, either to `recipient` (the
+// winner) or, when `recipient` is `None`, back to the depositor. The vault
+// and `Prize` rent is always returned to the depositor.
+fn release_prizes<'info>(
+  token_lottery: &mut Account<'info, TokenLottery>,
+  token_program: &Interface<'info, TokenInterface>,
+  remaining_accounts: &'info [AccountInfo<'info>],
+  recipient: Option<Pubkey>,
+) -> Result<()> {
+  let prizes = remaining_accounts.chunks_exact(PRIZE_ACCOUNTS_LEN);
+  require!(prizes.remainder().is_empty(), ErrorCode::IncorrectPrizeAccounts);
+
+  let lottery_key = token_lottery.key();
+  let authority = token_lottery.authority;
+  let id = token_lottery.id.to_le_bytes();
+  let bump = token_lottery.bump;
+  let signer_seeds: &[&[&[u8]]] = &[&[
+    b"token_lottery".as_ref(),
+    authority.as_ref(),
+    id.as_ref(),
+    &[bump]
+  ]];
+
+  for accounts in prizes {
+    let prize = Account::<Prize>::try_from(&accounts[0])?;
+    let prize_vault = InterfaceAccount::<TokenAccount>::try_from(&accounts[1])?;
+    let prize_mint = InterfaceAccount::<Mint>::try_from(&accounts[2])?;
+    let destination = InterfaceAccount::<TokenAccount>::try_from(&accounts[3])?;
+    let depositor = &accounts[4];
+
+    require!(prize.lottery == lottery_key, ErrorCode::IncorrectPrizeAccounts);
+    require!(prize.vault == prize_vault.key(), ErrorCode::IncorrectPrizeAccounts);
+    require!(prize.mint == prize_mint.key(), ErrorCode::IncorrectPrizeAccounts);
+    require!(prize.depositor == depositor.key(), ErrorCode::IncorrectPrizeAccounts);
+    require!(destination.mint == prize.mint, ErrorCode::IncorrectPrizeAccounts);
+    require!(destination.owner == recipient.unwrap_or(prize.depositor), ErrorCode::IncorrectPrizeAccounts);
+
+    transfer_checked(
+      CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        TransferChecked {
+          from: prize_vault.to_account_info(),
+          mint: prize_mint.to_account_info(),
+          to: destination.to_account_info(),
+          authority: token_lottery.to_account_info(),
+        },
+        signer_seeds
+      ),
+      prize.amount,
+      prize_mint.decimals,
+    )?;
+
+    close_account(
+      CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+          account: prize_vault.to_account_info(),
+          destination: depositor.to_account_info(),
+          authority: token_lottery.to_account_info(),
+        },
+        signer_seeds
+      )
+    )?;
+
+    prize.close(depositor.to_account_info())?;
+
+    token_lottery.prize_count = token_lottery.prize_count.checked_sub(1).ok_or(ErrorCode::Overflow)?;
+  }
+
+  Ok(())
+}
+
+// Accounts needed to mint a ticket NFT into the lottery collection.
+pub struct TicketMintAccounts<'info> {
+  pub payer: AccountInfo<'info>,
+  pub ticket_mint: AccountInfo<'info>,
+  pub destination_token_account: AccountInfo<'info>,
+  // owner of `destination_token_account`
+  pub recipient: AccountInfo<'info>,
+  pub metadata: AccountInfo<'info>,
+  pub master_edition: AccountInfo<'info>,
+  pub collection_metadata: AccountInfo<'info>,
+  pub collection_master_edition: AccountInfo<'info>,
+  pub collection_mint: AccountInfo<'info>,
+  pub token_program: AccountInfo<'info>,
+  pub system_program: AccountInfo<'info>,
+  pub token_metadata_program: AccountInfo<'info>,
+  pub rent: AccountInfo<'info>,
+}
+
+// Mints the ticket, creates its metadata and master edition and verifies it
+// as an item of the sized lottery collection. Used by every path that
+// creates tickets so they all end up identical.
+fn mint_ticket<'info>(
+  accounts: TicketMintAccounts<'info>,
+  ticket_name: String,
+  ticket_freeze: TicketFreeze,
+  signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+  // the master edition takes over the freeze authority, after that the
+  // collection mint can only freeze the ticket as its delegate
+  let is_frozen = ticket_freeze.freezes_at_mint(accounts.recipient.is_signer)?;
+
+  // mint the ticket
+  let cpi_context = CpiContext::new_with_signer(
+    accounts.token_program.clone(),
+    MintTo {
+      mint: accounts.ticket_mint.clone(),
+      to: accounts.destination_token_account.clone(),
+      authority: accounts.collection_mint.clone()
+    },
+    signer_seeds
+  );
+
+  mint_to(
+    cpi_context,
+    1
+  )?;
+
+  if is_frozen {
+    approve(
+      CpiContext::new(
+        accounts.token_program.clone(),
+        Approve {
+          to: accounts.destination_token_account.clone(),
+          delegate: accounts.collection_mint.clone(),
+          authority: accounts.recipient.clone(),
+        }
+      ),
+      1
+    )?;
+  }
+
+  let cpi_context = CpiContext::new_with_signer(
+    accounts.token_metadata_program.clone(),
+    CreateMetadataAccountsV3 {
+      metadata: accounts.metadata.clone(),
+      mint: accounts.ticket_mint.clone(),
+      mint_authority: accounts.collection_mint.clone(),
+      payer: accounts.payer.clone(),
+      update_authority: accounts.collection_mint.clone(),
+      system_program: accounts.system_program.clone(),
+      rent: accounts.rent.clone()
+    },
+    signer_seeds
+  );
+  create_metadata_accounts_v3(
+    cpi_context,
+    DataV2 {
+      name: ticket_name,
+      symbol: SYMBOL.to_string(),
+      uri: URI.to_string(),
+      seller_fee_basis_points: 0,
+      creators: Some(vec![Creator {
+        address: accounts.collection_mint.key(),
+        verified: false,
+        share: 100
+      }]),
+      collection: None,
+      uses: None,
+    },
+    true,
+    true,
+    None,
+  )?;
+
+  let cpi_context = CpiContext::new_with_signer(
+    accounts.token_metadata_program.clone(),
+    CreateMasterEditionV3 {
+      payer: accounts.payer.clone(),
+      edition: accounts.master_edition.clone(),
+      mint: accounts.ticket_mint.clone(),
+      update_authority: accounts.collection_mint.clone(),
+      mint_authority: accounts.collection_mint.clone(),
+      metadata: accounts.metadata.clone(),
+      token_program: accounts.token_program.clone(),
+      system_program: accounts.system_program.clone(),
+      rent: accounts.rent.clone()
+    },
+    signer_seeds
+  );
+
+  create_master_edition_v3(
+    cpi_context,
+    Some(0)
+  )?;
+
+  let cpi_context = CpiContext::new_with_signer(
+    accounts.token_metadata_program.clone(),
+    SetAndVerifySizedCollectionItem {
+      metadata: accounts.metadata.clone(),
+      payer: accounts.payer.clone(),
+      collection_authority: accounts.collection_mint.clone(),
+      collection_master_edition: accounts.collection_master_edition.clone(),
+      collection_metadata: accounts.collection_metadata.clone(),
+      collection_mint: accounts.collection_mint.clone(),
+      update_authority: accounts.collection_mint.clone(),
+    },
+    signer_seeds
+  );
+
+  set_and_verify_sized_collection_item(
+    cpi_context,
+    None
+  )?;
+
+  if is_frozen {
+    freeze_delegated_account(
+      CpiContext::new_with_signer(
+        accounts.token_metadata_program.clone(),
+        FreezeDelegatedAccount {
+          metadata: accounts.metadata.clone(),
+          delegate: accounts.collection_mint.clone(),
+          token_account: accounts.destination_token_account.clone(),
+          edition: accounts.master_edition.clone(),
+          mint: accounts.ticket_mint.clone(),
+          token_program: accounts.token_program.clone(),
+        },
+        signer_seeds
+      )
+    )?;
+  }
+
+  Ok(())
+}
+
+// Checks that `token_account` holds the winning ticket of the lottery's
+// collection.
+fn check_winning_ticket(
+  token_lottery: &TokenLottery,
+  metadata: &MetadataAccount,
+  collection_mint: &Pubkey,
+  token_account: &TokenAccount,
+) -> Result<()> {
+  require!(token_lottery.is_winner_chosen, ErrorCode::WinnerNotChosen);
+
+  // Check if token is a part of the collection
+  verify_collection_item(metadata, *collection_mint)?;
+
+  let ticket_name = NAME.to_owned() + &token_lottery.winner.to_string();
+  let metadata_name = metadata.name.replace("\u{0}", "");
+
+  // Check if the token account holds the winning ticket
+  require!(metadata_name == ticket_name, ErrorCode::IncorrectTicket);
+  require!(token_account.amount > 0, ErrorCode::IncorrectTicket);
+  Ok(())
+}
+
+// Pays the pot to `payee` unless it was already paid. In no-loss mode the
+// other holders' principal stays in the pot for withdraw_principal.
+fn pay_pot<'info>(
+  token_lottery: &mut Account<'info, TokenLottery>,
+  ticket: &Ticket,
+  payee: &AccountInfo<'info>,
+) -> Result<()> {
+  if token_lottery.is_prize_claimed {
+    return Ok(());
+  }
+
+  let retained = if token_lottery.is_no_loss {
+    token_lottery.principal_amount.checked_sub(ticket.principal()).ok_or(ErrorCode::Overflow)?
+  } else {
+    0
+  };
+  let payout = token_lottery.lottery_pot_amount.checked_sub(retained).ok_or(ErrorCode::Overflow)?;
+
+  move_lamports(&token_lottery.to_account_info(), payee, payout)?;
+
+  token_lottery.lottery_pot_amount = retained;
+  token_lottery.principal_amount = retained;
+  token_lottery.is_prize_claimed = true;
+
+  emit!(PrizeClaimed {
+    lottery: token_lottery.key(),
+    ticket_mint: ticket.mint,
+    winner: payee.key(),
+    amount: payout,
+  });
+  Ok(())
+}
+
+// Whether `token_account` was frozen by `collection_mint` acting as the
+// ticket's delegate, i.e. through the lottery's `TicketFreeze` policy.
+fn is_frozen_by_lottery(token_account: &TokenAccount, collection_mint: &Pubkey) -> bool {
+  token_account.is_frozen() && token_account.delegate == COption::Some(*collection_mint)
+}
+
+// Thaws a ticket frozen by the lottery so it can be burned or moved again.
+// Tickets frozen by another delegate are left for the token program to reject.
+fn thaw_ticket<'info>(
+  token_lottery: &TokenLottery,
+  token_account: &TokenAccount,
+  accounts: ThawDelegatedAccount<'info>,
+  token_metadata_program: AccountInfo<'info>,
+) -> Result<()> {
+  if !is_frozen_by_lottery(token_account, accounts.delegate.key) {
+    return Ok(());
+  }
+
+  let authority = token_lottery.authority;
+  let collection_mint_bump = token_lottery.cached_collection_mint_bump();
+  let signer_seeds: &[&[&[u8]]] = &[&[
+    b"collection_mint".as_ref(),
+    authority.as_ref(),
+    &token_lottery.id.to_le_bytes(),
+    &[collection_mint_bump]
+  ]];
+
+  thaw_delegated_account(
+    CpiContext::new_with_signer(
+      token_metadata_program,
+      accounts,
+      signer_seeds
+    )
+  )
+}
+
+// Validates the proof accounts required by `token_lottery.purchase_gate`.
+fn check_gate(
+  token_lottery: &TokenLottery,
+  buyer: &Pubkey,
+  gate_token_account: Option<&TokenAccount>,
+  gate_metadata: Option<&MetadataAccount>,
+) -> Result<()> {
+  match token_lottery.purchase_gate {
+    PurchaseGate::None => {}
+    PurchaseGate::TokenBalance { mint, min_amount } => {
+      let gate_token_account = gate_token_account.ok_or(ErrorCode::GateNotSatisfied)?;
+      require!(gate_token_account.mint == mint, ErrorCode::GateNotSatisfied);
+      require!(gate_token_account.owner == *buyer, ErrorCode::GateNotSatisfied);
+      require!(gate_token_account.amount >= min_amount, ErrorCode::GateNotSatisfied);
+    }
+    PurchaseGate::Collection { collection_mint } => {
+      let gate_token_account = gate_token_account.ok_or(ErrorCode::GateNotSatisfied)?;
+      let gate_metadata = gate_metadata.ok_or(ErrorCode::GateNotSatisfied)?;
+      require!(gate_token_account.owner == *buyer, ErrorCode::GateNotSatisfied);
+      require!(gate_token_account.amount > 0, ErrorCode::GateNotSatisfied);
+      require!(gate_metadata.mint == gate_token_account.mint, ErrorCode::GateNotSatisfied);
+      verify_collection_item(gate_metadata, collection_mint).map_err(|_| ErrorCode::GateNotSatisfied)?;
+    }
+  }
+  Ok(())
+}
+
+// Sends the referrer's share of `price` to its `Referral` account and
+// returns it, the share never becomes part of the pot.
+fn pay_referral<'info>(
+  token_lottery: &Account<'info, TokenLottery>,
+  buyer: &Signer<'info>,
+  referral: Option<&mut Account<'info, Referral>>,
+  system_program: &Program<'info, System>,
+  price: u64,
+) -> Result<u64> {
+  let Some(referral) = referral else {
+    return Ok(0);
+  };
+  require!(referral.lottery == token_lottery.key(), ErrorCode::IncorrectReferral);
+  require!(referral.referrer != buyer.key(), ErrorCode::SelfReferral);
+
+  let fee = token_lottery.referral_fee(price)?;
+  if fee == 0 {
+    return Ok(0);
+  }
+
+  system_program::transfer(
+    CpiContext::new(
+      system_program.to_account_info(),
+      system_program::Transfer {
+        from: buyer.to_account_info(),
+        to: referral.to_account_info(),
+      }
+    ),
+    fee,
+  )?;
+
+  referral.accrued = referral.accrued.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+
+  Ok(fee)
+}
+
+// Appends the newly minted ticket to the weight tree of weighted lotteries.
+fn record_entry(token_lottery: &Account<TokenLottery>, entry_weights: Option<&AccountLoader<EntryWeights>>) -> Result<()> {
+  if !token_lottery.is_weighted {
+    return Ok(());
+  }
+  let mut entry_weights = entry_weights.ok_or(ErrorCode::EntryWeightsRequired)?.load_mut()?;
+  entry_weights.record(token_lottery.key(), token_lottery.number_of_tickets)
+}
+
+// Randomness that wasn't revealed since its commit and whose slot hash has
+// left the reveal window.
+fn is_randomness_expired(randomness: &RandomnessAccountData, slot: u64) -> bool {
+  randomness.reveal_slot < randomness.seed_slot
+    && slot > randomness.seed_slot.saturating_add(REVEAL_WINDOW_SLOTS)
+}
+
+// Receipt of a ticket sold before `migrate_lottery`, v1 tickets are only
+// known by the index in their metadata name and all paid the flat price.
+fn backfilled_ticket(
+  token_lottery: &TokenLottery,
+  lottery: Pubkey,
+  metadata: &MetadataAccount,
+  collection_mint: Pubkey,
+  bump: u8,
+) -> Result<Ticket> {
+  verify_collection_item(metadata, collection_mint)?;
+
+  let index = metadata.name
+    .replace("\u{0}", "")
+    .strip_prefix(NAME)
+    .and_then(|index| index.parse::<u64>().ok())
+    .ok_or(ErrorCode::IncorrectTicket)?;
+  require!(index < token_lottery.v1_tickets, ErrorCode::IncorrectTicket);
+
+  Ok(Ticket {
+    lottery,
+    mint: metadata.mint,
+    index,
+    price_paid: token_lottery.price,
+    referral_fee: 0,
+    referrer: Pubkey::default(),
+    bump,
+  })
+}
+
+// Checks that `metadata` belongs to a verified item of `collection_mint`.
+fn verify_collection_item(metadata: &MetadataAccount, collection_mint: Pubkey) -> Result<()> {
+  let collection = metadata.collection.as_ref().ok_or(ErrorCode::NotVerifiedTicket)?;
+  require!(collection.verified, ErrorCode::NotVerifiedTicket);
+  require!(collection.key == collection_mint, ErrorCode::IncorrectTicket);
+  Ok(())
+}
+
+// Collection mint PDA of lottery `id` created by `authority`.
+fn collection_mint_address(authority: &Pubkey, id: u64) -> (Pubkey, u8) {
+  Pubkey::find_program_address(
+    &[b"collection_mint".as_ref(), authority.as_ref(), id.to_le_bytes().as_ref()],
+    &crate::ID,
+  )
+}
+
+// Moves lamports out of an account owned by this program, failing instead
+// of wrapping when `from` holds less than `amount`.
+fn move_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+  let from_lamports = from.lamports().checked_sub(amount).ok_or(ErrorCode::InsufficientFunds)?;
+  let to_lamports = to.lamports().checked_add(amount).ok_or(ErrorCode::Overflow)?;
+  **from.try_borrow_mut_lamports()? = from_lamports;
+  **to.try_borrow_mut_lamports()? = to_lamports;
+  Ok(())
+}
+
+// Checks a keccak merkle proof built with sorted pairs.
+fn verify_merkle_proof(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+  let computed = proof.iter().fold(leaf, |node, sibling| {
+    if node <= *sibling {
+      keccak::hashv(&[&node, sibling]).to_bytes()
+    } else {
+      keccak::hashv(&[sibling, &node]).to_bytes()
+    }
+  });
+  computed == root
+}
+
+#[derive(Accounts)]
+pub struct InitializeRegistry<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+      init,
+      payer = payer,
+      space = 8 + Registry::INIT_SPACE,
+      seeds = [b"registry".as_ref()],
+      bump
+    )]
+    pub registry: Account<'info, Registry>,
+
+    pub system_program: Program<'info, System>
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+      mut,
+      seeds = [b"registry".as_ref()],
+      bump = registry.bump,
+    )]
+    pub registry: Account<'info, Registry>,
+
+    #[account(
+      init_if_needed,
+      payer = payer,
+      space = 8 + AuthorityIndex::INIT_SPACE,
+      seeds = [
+        b"authority_index".as_ref(),
+        payer.key().as_ref(),
+      ],
+      bump
+    )]
+    pub authority_index: Account<'info, AuthorityIndex>,
+
+    #[account(
+      init,
+      payer = payer,
+      space = 8 + AuthorityLottery::INIT_SPACE,
+      seeds = [
+        b"authority_lottery".as_ref(),
+        payer.key().as_ref(),
+        authority_index.lottery_count.to_le_bytes().as_ref(),
+      ],
+      bump
+    )]
+    pub authority_lottery: Account<'info, AuthorityLottery>,
+
+    #[account(
+      init,
+      payer = payer,
+      space = 8 + TokenLottery::INIT_SPACE,
+      seeds = [
+        b"token_lottery".as_ref(),
+        payer.key().as_ref(), 
+        registry.lottery_count.to_le_bytes().as_ref(),
+      ],
+      bump
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    pub system_program: Program<'info, System>
+
+}
+
+#[derive(Accounts)]
+pub struct MigrateLottery<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: v1 accounts do not deserialize as `TokenLottery`, checked in `from_v1_data`
+    #[account(mut, owner = crate::ID)]
+    pub token_lottery: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Creates the `Ticket` receipt the claim and refund instructions expect for
+// a ticket sold before the lottery was migrated.
+#[derive(Accounts)]
+pub struct BackfillTicket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    pub ticket_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+      init,
+      payer = payer,
+      space = 8 + Ticket::INIT_SPACE,
+      seeds = [
+        b"ticket".as_ref(),
+        ticket_mint.key().as_ref(),
+      ],
+      bump
+    )]
+    pub ticket: Account<'info, Ticket>,
+
+    #[account(
+      seeds = [
+        b"metadata".as_ref(),
+        token_metadata_program.key().as_ref(),
+        ticket_mint.key().as_ref()
+      ],
+      bump,
+      seeds::program = token_metadata_program.key(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+      seeds = [
+        b"collection_mint".as_ref(),
+        token_lottery.authority.as_ref(),
+        token_lottery.id.to_le_bytes().as_ref(),
+      ],
+      bump = token_lottery.cached_collection_mint_bump(),
+    )]
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct InitializeLottery<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    #[account(
+      init,
+      payer = payer,
+      mint::authority = collection_mint,
+      mint::decimals = 0,
+      mint::freeze_authority = collection_mint,
+      seeds = [
+        b"collection_mint".as_ref(),
+        payer.key().as_ref(),
+        id.to_le_bytes().as_ref(),
+      ],
+      bump,
+    )]
+    pub collection_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    /// CHECK: This account will be initialized by the metaplex program
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: This account will be initialized by the metaplex program
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+      init_if_needed,
+      payer = payer,
+      token::mint = collection_mint,
+      token::authority = collection_mint,
+      seeds = [
+        b"collection_token_account".as_ref(),
+        payer.key().as_ref(),
+        id.to_le_bytes().as_ref(),
+      ],
+      bump
+    )]
+    pub collection_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(Accounts)]
+pub struct ConfigurePricing<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+pub struct BuyTicket<'info> {
+    // pays rent and fees for the new accounts, e.g. a relayer
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // pays the ticket price
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: receives the ticket, can be any wallet
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Box<Account<'info, TokenLottery>>,
+
+    #[account(
+      init,
+      payer = payer,
+      space = 8 + Ticket::INIT_SPACE,
+      seeds = [
+        b"ticket".as_ref(),
+        ticket_mint.key().as_ref(),
+      ],
+      bump
+    )]
+    pub ticket: Box<Account<'info, Ticket>>,
+
+    #[account(
+      init,
+      payer = payer,
+      seeds = [
+        b"ticket_mint".as_ref(),
+        buyer.key().as_ref(),
+        token_lottery.id.to_le_bytes().as_ref(),
+        token_lottery.number_of_tickets.to_le_bytes().as_ref()
+      ],
+      bump,
+      mint::authority = collection_mint,
+      mint::decimals = 0,
+      mint::freeze_authority = collection_mint,
+      mint::token_program = token_program
+    )]
+    pub ticket_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+      init,
+      payer = payer,
+      associated_token::mint = ticket_mint,
+      associated_token::authority = recipient,
+      associated_token::token_program = token_program,
+    )]
+    pub destination_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // the Metaplex program checks the derivation of these four accounts
+    // itself, re-deriving them here only costs compute
+    #[account(mut)]
+    /// CHECK: This account will be initialized by the metaplex program
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: This account will be initialized by the metaplex program
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: checked by the metaplex program when verifying the ticket
+    pub collection_metadata: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: checked by the metaplex program when verifying the ticket
+    pub collection_master_edition: UncheckedAccount<'info>,
+
+    #[account(
+      mut,
+      seeds = [
+        b"collection_mint",
+        token_lottery.authority.as_ref(),
+        token_lottery.id.to_le_bytes().as_ref(),
+      ],
+      bump = token_lottery.cached_collection_mint_bump(),
+    )]
+    pub collection_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    // proof accounts for gated lotteries, see `PurchaseGate`
+    pub gate_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub gate_metadata: Option<Box<Account<'info, MetadataAccount>>>,
+
+    #[account(mut)]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    // required for weighted lotteries
+    #[account(
+      mut,
+      seeds = [
+        b"entry_weights".as_ref(),
+        token_lottery.key().as_ref(),
+      ],
+      bump
+    )]
+    pub entry_weights: Option<AccountLoader<'info, EntryWeights>>,
+
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+impl<'info> BuyTicket<'info> {
+  // Takes payment and mints a verified ticket of the lottery collection,
+  // shared by every purchase path.
+  pub fn purchase(&mut self, bumps: &BuyTicketBumps, slot: u64, max_price: u64) -> Result<()> {
+    check_gate(
+      &self.token_lottery,
+      &self.buyer.key(),
+      self.gate_token_account.as_deref().map(|account| &**account),
+      self.gate_metadata.as_deref().map(|account| &**account),
+    )?;
+    // own tickets can't run into the range of the first shard
+    require!(
+      self.token_lottery.shard_count == 0 || self.token_lottery.number_of_tickets < TICKETS_PER_SHARD,
+      ErrorCode::ShardFull
+    );
+
+    let ticket_name = NAME.to_owned() + self.token_lottery.number_of_tickets.to_string().as_str();
+
+    let price = self.token_lottery.current_price(slot)?;
+    require!(price <= max_price, ErrorCode::PriceAboveMax);
+
+    let referral_fee = pay_referral(
+      &self.token_lottery,
+      &self.buyer,
+      self.referral.as_deref_mut(),
+      &self.system_program,
+      price,
+    )?;
+    self.token_lottery.referral_total = self.token_lottery.referral_total.checked_add(referral_fee).ok_or(ErrorCode::Overflow)?;
+    let pot_share = price.checked_sub(referral_fee).ok_or(ErrorCode::Overflow)?;
+
+    // transfer solana
+    system_program::transfer(
+      CpiContext::new(
+        self.system_program.to_account_info(),
+        system_program::Transfer {
+          from: self.buyer.to_account_info(),
+          to: self.token_lottery.to_account_info(),
+        }
+      ),
+      pot_share,
+    )?;
+
+    self.token_lottery.lottery_pot_amount = self.token_lottery.lottery_pot_amount.checked_add(pot_share).ok_or(ErrorCode::Overflow)?;
+    if self.token_lottery.is_no_loss {
+      self.token_lottery.principal_amount = self.token_lottery.principal_amount.checked_add(pot_share).ok_or(ErrorCode::Overflow)?;
+    }
+
+    self.ticket.lottery = self.token_lottery.key();
+    self.ticket.mint = self.ticket_mint.key();
+    self.ticket.index = self.token_lottery.number_of_tickets;
+    self.ticket.price_paid = price;
+    self.ticket.referral_fee = referral_fee;
+    self.ticket.referrer = self.referral.as_ref().map_or(Pubkey::default(), |referral| referral.referrer);
+    self.ticket.bump = bumps.ticket;
+
+    let authority = self.token_lottery.authority;
+    let collection_mint_bump = self.token_lottery.cached_collection_mint_bump();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+      b"collection_mint".as_ref(),
+      authority.as_ref(),
+      &self.token_lottery.id.to_le_bytes(),
+      &[collection_mint_bump]
+    ]];
+
+    mint_ticket(
+      TicketMintAccounts {
+        payer: self.payer.to_account_info(),
+        ticket_mint: self.ticket_mint.to_account_info(),
+        destination_token_account: self.destination_token_account.to_account_info(),
+        recipient: self.recipient.to_account_info(),
+        metadata: self.metadata.to_account_info(),
+        master_edition: self.master_edition.to_account_info(),
+        collection_metadata: self.collection_metadata.to_account_info(),
+        collection_master_edition: self.collection_master_edition.to_account_info(),
+        collection_mint: self.collection_mint.to_account_info(),
+        token_program: self.token_program.to_account_info(),
+        system_program: self.system_program.to_account_info(),
+        token_metadata_program: self.token_metadata_program.to_account_info(),
+        rent: self.rent.to_account_info(),
+      },
+      ticket_name,
+      self.token_lottery.ticket_freeze,
+      signer_seeds,
+    )?;
+
+    record_entry(&self.token_lottery, self.entry_weights.as_ref())?;
+
+    emit!(TicketPurchased {
+      lottery: self.token_lottery.key(),
+      ticket_mint: self.ticket_mint.key(),
+      index: self.ticket.index,
+      buyer: self.buyer.key(),
+      recipient: self.recipient.key(),
+      price_paid: price,
+      referral_fee,
+      slot,
+    });
+
+    self.token_lottery.number_of_tickets = self.token_lottery.number_of_tickets.checked_add(1).ok_or(ErrorCode::Overflow)?;
+
+    Ok(())
+  }
+}
+
+#[derive(Accounts)]
+pub struct BuyTicketAllowlisted<'info> {
+    pub buy: BuyTicket<'info>,
+
+    #[account(
+      init_if_needed,
+      payer = buy.payer,
+      space = 8 + AllowlistUsage::INIT_SPACE,
+      seeds = [
+        b"allowlist".as_ref(),
+        buy.token_lottery.key().as_ref(),
+        buy.buyer.key().as_ref(),
+      ],
+      bump
+    )]
+    pub allowlist_usage: Account<'info, AllowlistUsage>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct IssueTicket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: any wallet can receive a promotional ticket
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+      init,
+      payer = payer,
+      space = 8 + Ticket::INIT_SPACE,
+      seeds = [
+        b"ticket".as_ref(),
+        ticket_mint.key().as_ref(),
+      ],
+      bump
+    )]
+    pub ticket: Account<'info, Ticket>,
+
+    #[account(
+      init,
+      payer = payer,
+      seeds = [
+        b"ticket_mint".as_ref(),
+        payer.key().as_ref(),
+        token_lottery.id.to_le_bytes().as_ref(),
+        token_lottery.number_of_tickets.to_le_bytes().as_ref()
+      ],
+      bump,
+      mint::authority = collection_mint,
+      mint::decimals = 0,
+      mint::freeze_authority = collection_mint,
+      mint::token_program = token_program
+    )]
+    pub ticket_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+      init,
+      payer = payer,
+      associated_token::mint = ticket_mint,
+      associated_token::authority = recipient,
+      associated_token::token_program = token_program,
+    )]
+    pub destination_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // derivations are checked by the metaplex program, see `BuyTicket`
+    #[account(mut)]
+    /// CHECK: This account will be initialized by the metaplex program
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: This account will be initialized by the metaplex program
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: checked by the metaplex program when verifying the ticket
+    pub collection_metadata: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: checked by the metaplex program when verifying the ticket
+    pub collection_master_edition: UncheckedAccount<'info>,
+
+    #[account(
+      mut,
+      seeds = [
+        b"collection_mint",
+        token_lottery.authority.as_ref(),
+        token_lottery.id.to_le_bytes().as_ref(),
+      ],
+      bump = token_lottery.cached_collection_mint_bump(),
+    )]
+    pub collection_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    // required for weighted lotteries
+    #[account(
+      mut,
+      seeds = [
+        b"entry_weights".as_ref(),
+        token_lottery.key().as_ref(),
+      ],
+      bump
+    )]
+    pub entry_weights: Option<AccountLoader<'info, EntryWeights>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigurePromo<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeEntryWeights<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+      init,
+      payer = payer,
+      space = 8 + std::mem::size_of::<EntryWeights>(),
+      seeds = [
+        b"entry_weights".as_ref(),
+        token_lottery.key().as_ref(),
+      ],
+      bump
+    )]
+    pub entry_weights: AccountLoader<'info, EntryWeights>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetEntryWeight<'info> {
+    pub payer: Signer<'info>,
+
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+      mut,
+      seeds = [
+        b"entry_weights".as_ref(),
+        token_lottery.key().as_ref(),
+      ],
+      bump
+    )]
+    pub entry_weights: AccountLoader<'info, EntryWeights>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureGate<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureTicketFreeze<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureReferrals<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterReferrer<'info> {
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+      init,
+      payer = referrer,
+      space = 8 + Referral::INIT_SPACE,
+      seeds = [
+        b"referral".as_ref(),
+        token_lottery.key().as_ref(),
+        referrer.key().as_ref(),
+      ],
+      bump
+    )]
+    pub referral: Account<'info, Referral>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+      mut,
+      seeds = [
+        b"referral".as_ref(),
+        token_lottery.key().as_ref(),
+        referrer.key().as_ref(),
+      ],
+      bump = referral.bump,
+    )]
+    pub referral: Account<'info, Referral>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureAllowlist<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+pub struct CommitWinner<'info> {
+  #[account(mut)]
+  pub payer: Signer<'info>,
+
+  #[account(mut)]
+  pub token_lottery: Account<'info, TokenLottery>,
+  /// CHECK: parsed as a Switchboard randomness account by the handler
+  #[account(owner = SWITCHBOARD_PROGRAM_ID @ ErrorCode::InvalidRandomnessAccount)]
+  pub randomness_account_data: UncheckedAccount<'info>,
+  
+  pub system_program: Program<'info, System>,
+}
+
+
+#[derive(Accounts)]
+pub struct RecommitWinner<'info> {
+  pub payer: Signer<'info>,
+
+  #[account(mut)]
+  pub token_lottery: Account<'info, TokenLottery>,
+  /// CHECK: the randomness committed to, parsed by the handler
+  #[account(owner = SWITCHBOARD_PROGRAM_ID @ ErrorCode::InvalidRandomnessAccount)]
+  pub committed_randomness_account_data: UncheckedAccount<'info>,
+  /// CHECK: parsed as a Switchboard randomness account by the handler
+  #[account(owner = SWITCHBOARD_PROGRAM_ID @ ErrorCode::InvalidRandomnessAccount)]
+  pub randomness_account_data: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ChooseWinner<'info> {
+  #[account(mut)]
+  pub payer: Signer<'info>,
+
+  #[account(mut)]
+  pub token_lottery: Account<'info, TokenLottery>,
+  /// CHECK: parsed as a Switchboard randomness account by the handler
+  #[account(owner = SWITCHBOARD_PROGRAM_ID @ ErrorCode::InvalidRandomnessAccount)]
+  pub randomness_account_data: UncheckedAccount<'info>,
+
+  // required for weighted lotteries
+  pub entry_weights: Option<AccountLoader<'info, EntryWeights>>,
+
+  #[account(
+    init,
+    payer = payer,
+    space = 8 + DrawResult::INIT_SPACE,
+    seeds = [
+      b"draw_result".as_ref(),
+      token_lottery.key().as_ref(),
+    ],
+    bump
+  )]
+  pub draw_result: Account<'info, DrawResult>,
+  
+  pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseLottery<'info> {
+  #[account(mut)]
+  pub payer: Signer<'info>,
+
+  #[account(mut, close = payer)]
+  pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPrize<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(mut)]
+    pub ticket_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+      constraint = ticket.lottery == token_lottery.key() @ ErrorCode::IncorrectTicket,
+      seeds = [
+        b"ticket".as_ref(),
+        ticket_mint.key().as_ref(),
+      ],
+      bump = ticket.bump,
+    )]
+    pub ticket: Account<'info, Ticket>,
+
+    #[account(
+      mut,
+      associated_token::mint = ticket_mint,
+      associated_token::authority = payer,
+      associated_token::token_program = token_program,
+    )]
+    pub destination_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    /// CHECK: checked by the metaplex program when thawing the ticket
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+      seeds = [
+        b"metadata".as_ref(),
+        token_metadata_program.key().as_ref(),
+        ticket_mint.key().as_ref()
+      ],
+      bump,
+      seeds::program = token_metadata_program.key(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+      mut,
+      seeds = [
+        b"metadata".as_ref(),
+        token_metadata_program.key().as_ref(),
+        collection_mint.key().as_ref()
+      ],
+      bump,
+      seeds::program = token_metadata_program.key()
+    )]
+    pub collection_metadata: Account<'info, MetadataAccount>,
+
+
+    #[account(
+      mut,
+      seeds = [
+        b"collection_mint".as_ref(),
+        token_lottery.authority.as_ref(),
+        token_lottery.id.to_le_bytes().as_ref(),
+      ],
+      bump = token_lottery.cached_collection_mint_bump(),
+    )]
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, Metadata>,
+    
+}
+
+// Permissionless version of `ClaimPrize` for wallets that can't sign, e.g.
+// a PDA vault, or that bought the ticket from someone else.
+#[derive(Accounts)]
+pub struct ClaimPrizeFor<'info> {
+    /// CHECK: current holder of the winning ticket, checked through its token account
+    pub holder: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: the holder, or the wallet it designated with `designate_prize_recipient`
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    pub ticket_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+      constraint = ticket.lottery == token_lottery.key() @ ErrorCode::IncorrectTicket,
+      seeds = [
+        b"ticket".as_ref(),
+        ticket_mint.key().as_ref(),
+      ],
+      bump = ticket.bump,
+    )]
+    pub ticket: Account<'info, Ticket>,
+
+    // any token account of the holder, not only its associated one
+    #[account(
+      mut,
+      token::mint = ticket_mint,
+      token::authority = holder,
+      token::token_program = token_program,
+    )]
+    pub holder_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    /// CHECK: checked by the metaplex program when thawing the ticket
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+      seeds = [
+        b"metadata".as_ref(),
+        token_metadata_program.key().as_ref(),
+        ticket_mint.key().as_ref()
+      ],
+      bump,
+      seeds::program = token_metadata_program.key(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+      seeds = [
+        b"collection_mint".as_ref(),
+        token_lottery.authority.as_ref(),
+        token_lottery.id.to_le_bytes().as_ref(),
+      ],
+      bump = token_lottery.cached_collection_mint_bump(),
+    )]
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    // always passed, so that callers can't skip a designated recipient
+    #[account(
+      seeds = [
+        b"prize_recipient".as_ref(),
+        ticket_mint.key().as_ref(),
+      ],
+      bump,
+    )]
+    /// CHECK: a `PrizeRecipient` or an empty account, see `PrizeRecipient::designated`
+    pub prize_recipient: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
+#[derive(Accounts)]
+pub struct DesignatePrizeRecipient<'info> {
+    #[account(mut)]
+    pub holder: Signer<'info>,
+
+    pub ticket_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+      token::mint = ticket_mint,
+      token::authority = holder,
+      constraint = holder_token_account.amount > 0 @ ErrorCode::IncorrectTicket,
+    )]
+    pub holder_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // replaced by every new holder
+    #[account(
+      init_if_needed,
+      payer = holder,
+      space = 8 + PrizeRecipient::INIT_SPACE,
+      seeds = [
+        b"prize_recipient".as_ref(),
+        ticket_mint.key().as_ref(),
+      ],
+      bump
+    )]
+    pub prize_recipient: Account<'info, PrizeRecipient>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundPot<'info> {
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+      init_if_needed,
+      payer = sponsor,
+      space = 8 + Sponsorship::INIT_SPACE,
+      seeds = [
+        b"sponsorship".as_ref(),
+        token_lottery.key().as_ref(),
+        sponsor.key().as_ref(),
+      ],
+      bump
+    )]
+    pub sponsorship: Account<'info, Sponsorship>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelLottery<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct DepositPrize<'info> {
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+      mint::token_program = token_program,
+    )]
+    pub prize_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+      mut,
+      token::mint = prize_mint,
+      token::authority = depositor,
+      token::token_program = token_program,
+    )]
+    pub depositor_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+      seeds = [
+        b"prize_depositor".as_ref(),
+        token_lottery.key().as_ref(),
+        depositor.key().as_ref(),
+      ],
+      bump,
+    )]
+    /// CHECK: a `PrizeDepositor` or an empty account, see `PrizeDepositor::is_approved`
+    pub prize_depositor: UncheckedAccount<'info>,
+
+    #[account(
+      init_if_needed,
+      payer = depositor,
+      space = 8 + Prize::INIT_SPACE,
+      seeds = [
+        b"prize".as_ref(),
+        token_lottery.key().as_ref(),
+        prize_mint.key().as_ref(),
+      ],
+      bump
+    )]
+    pub prize: Account<'info, Prize>,
+
+    #[account(
+      init_if_needed,
+      payer = depositor,
+      token::mint = prize_mint,
+      token::authority = token_lottery,
+      token::token_program = token_program,
+      seeds = [
+        b"prize_vault".as_ref(),
+        token_lottery.key().as_ref(),
+        prize_mint.key().as_ref(),
+      ],
+      bump
+    )]
+    pub prize_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(depositor: Pubkey)]
+pub struct ApprovePrizeDepositor<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
       init,
       payer = payer,
-      space = 8 + TokenLottery::INIT_SPACE,
+      space = 8 + PrizeDepositor::INIT_SPACE,
+      seeds = [
+        b"prize_depositor".as_ref(),
+        token_lottery.key().as_ref(),
+        depositor.as_ref(),
+      ],
+      bump
+    )]
+    pub prize_depositor: Account<'info, PrizeDepositor>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokePrizeDepositor<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+      mut,
+      close = payer,
+      seeds = [
+        b"prize_depositor".as_ref(),
+        token_lottery.key().as_ref(),
+        prize_depositor.depositor.as_ref(),
+      ],
+      bump = prize_depositor.bump,
+    )]
+    pub prize_depositor: Account<'info, PrizeDepositor>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRefund<'info> {
+    #[account(mut)]
+    pub holder: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(mut)]
+    pub ticket_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+      mut,
+      close = holder,
+      constraint = ticket.lottery == token_lottery.key() @ ErrorCode::IncorrectTicket,
       seeds = [
-        b"token_lottery".as_ref(),
-        payer.key().as_ref(), 
-        id.to_le_bytes().as_ref(),
+        b"ticket".as_ref(),
+        ticket_mint.key().as_ref(),
       ],
-      bump
+      bump = ticket.bump,
     )]
-    pub token_lottery: Account<'info, TokenLottery>,
-
-    pub system_program: Program<'info, System>
+    pub ticket: Account<'info, Ticket>,
 
-}
+    #[account(
+      mut,
+      token::mint = ticket_mint,
+      token::authority = holder,
+      token::token_program = token_program,
+    )]
+    pub holder_token_account: InterfaceAccount<'info, TokenAccount>,
 
-#[derive(Accounts)]
-#[instruction(id: u64)]
-pub struct InitializeLottery<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    
     #[account(
-      init,
-      payer = payer,
-      mint::authority = collection_mint,
-      mint::decimals = 0,
-      mint::freeze_authority = collection_mint,
+      mut,
       seeds = [
-        b"collection_mint".as_ref(),
-        payer.key().as_ref(),
-        id.to_le_bytes().as_ref(),
+        b"metadata".as_ref(),
+        token_metadata_program.key().as_ref(),
+        ticket_mint.key().as_ref()
       ],
       bump,
+      seeds::program = token_metadata_program.key(),
     )]
-    pub collection_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub metadata: Account<'info, MetadataAccount>,
 
     #[account(mut)]
-    /// CHECK: This account will be initialized by the metaplex program
-    pub metadata: UncheckedAccount<'info>,
+    /// CHECK: checked by the metaplex program when thawing and burning the ticket
+    pub master_edition: UncheckedAccount<'info>,
 
     #[account(mut)]
-    /// CHECK: This account will be initialized by the metaplex program
-    pub master_edition: UncheckedAccount<'info>,
+    /// CHECK: checked by the metaplex program when shrinking the collection
+    pub collection_metadata: UncheckedAccount<'info>,
 
     #[account(
-      init_if_needed,
-      payer = payer,
-      token::mint = collection_mint,
-      token::authority = collection_mint,
       seeds = [
-        b"collection_token_account".as_ref(),
-        payer.key().as_ref(),
-        id.to_le_bytes().as_ref(),
+        b"collection_mint".as_ref(),
+        token_lottery.authority.as_ref(),
+        token_lottery.id.to_le_bytes().as_ref(),
       ],
-      bump
+      bump = token_lottery.cached_collection_mint_bump(),
     )]
-    pub collection_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub collection_mint: InterfaceAccount<'info, Mint>,
 
+    // referrer of the ticket, required to refund tickets that paid a referral fee
+    #[account(mut)]
+    pub referral: Option<Account<'info, Referral>>,
 
     pub token_program: Interface<'info, TokenInterface>,
-    pub system_program: Program<'info, System>,
     pub token_metadata_program: Program<'info, Metadata>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
+impl<'info> ClaimRefund<'info> {
+  // Burns the ticket, which makes sure it can only be paid back once, and
+  // returns its principal from the pot. Refunds of a lottery that won't draw
+  // also take the referral fee back from the referrer, repaying the full price.
+  fn burn_and_refund(&mut self, refund_referral_fee: bool) -> Result<()> {
+    // shards still hold their part of the pot
+    require!(!self.token_lottery.is_sharding_pending(), ErrorCode::ShardsNotFinalized);
+
+    thaw_ticket(
+      &self.token_lottery,
+      &self.holder_token_account,
+      ThawDelegatedAccount {
+        metadata: self.metadata.to_account_info(),
+        delegate: self.collection_mint.to_account_info(),
+        token_account: self.holder_token_account.to_account_info(),
+        edition: self.master_edition.to_account_info(),
+        mint: self.ticket_mint.to_account_info(),
+        token_program: self.token_program.to_account_info(),
+      },
+      self.token_metadata_program.to_account_info(),
+    )?;
+
+    // like `burn_ticket`, hands the rent of the token account, metadata and
+    // master edition back to the holder
+    burn_nft(
+      CpiContext::new(
+        self.token_metadata_program.to_account_info(),
+        BurnNft {
+          metadata: self.metadata.to_account_info(),
+          owner: self.holder.to_account_info(),
+          mint: self.ticket_mint.to_account_info(),
+          token: self.holder_token_account.to_account_info(),
+          edition: self.master_edition.to_account_info(),
+          spl_token: self.token_program.to_account_info(),
+        }
+      ).with_remaining_accounts(vec![self.collection_metadata.to_account_info()]),
+      Some(self.collection_metadata.key()),
+    )?;
+
+    let refund = self.ticket.principal();
+    self.token_lottery.lottery_pot_amount = self.token_lottery.lottery_pot_amount.checked_sub(refund).ok_or(ErrorCode::Overflow)?;
+    if self.token_lottery.is_no_loss {
+      self.token_lottery.principal_amount = self.token_lottery.principal_amount.checked_sub(refund).ok_or(ErrorCode::Overflow)?;
+    }
+
+    move_lamports(&self.token_lottery.to_account_info(), &self.holder.to_account_info(), refund)?;
+
+    let mut referral_fee = 0;
+    if refund_referral_fee && self.ticket.referral_fee > 0 {
+      let referral = self.referral.as_mut().ok_or(ErrorCode::IncorrectReferral)?;
+      referral_fee = referral.take_back_fee(&self.ticket)?;
+      self.token_lottery.referral_total = self.token_lottery.referral_total.checked_sub(referral_fee).ok_or(ErrorCode::Overflow)?;
+      move_lamports(&referral.to_account_info(), &self.holder.to_account_info(), referral_fee)?;
+    }
+
+    emit!(TicketRefunded {
+      lottery: self.token_lottery.key(),
+      ticket_mint: self.ticket_mint.key(),
+      holder: self.holder.key(),
+      amount: refund.checked_add(referral_fee).ok_or(ErrorCode::Overflow)?,
+    });
+
+    Ok(())
+  }
+}
+
+#[derive(Accounts)]
+pub struct ConfigureNoLoss<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
 
 #[derive(Accounts)]
-pub struct BuyTicket<'info> {
+pub struct ReclaimSponsorship<'info> {
     #[account(mut)]
-    pub payer: Signer<'info>,
+    pub sponsor: Signer<'info>,
 
     #[account(mut)]
     pub token_lottery: Account<'info, TokenLottery>,
 
     #[account(
-      init,
-      payer = payer,
+      mut,
+      close = sponsor,
       seeds = [
-        b"ticket_mint".as_ref(),
-        payer.key().as_ref(),
-        token_lottery.id.to_le_bytes().as_ref(),
-        token_lottery.number_of_tickets.to_le_bytes().as_ref()
+        b"sponsorship".as_ref(),
+        token_lottery.key().as_ref(),
+        sponsor.key().as_ref(),
       ],
-      bump,
-      mint::authority = collection_mint,
-      mint::decimals = 0,
-      mint::freeze_authority = collection_mint,
-      mint::token_program = token_program
+      bump = sponsorship.bump,
     )]
-    pub ticket_mint: InterfaceAccount<'info, Mint>,
+    pub sponsorship: Account<'info, Sponsorship>,
+}
+
+#[derive(Accounts)]
+#[instruction(shard: u8)]
+pub struct InitializeTicketShard<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
 
     #[account(
       init,
       payer = payer,
-      associated_token::mint = ticket_mint,
-      associated_token::authority = payer,
-      associated_token::token_program = token_program,
+      space = 8 + TicketShard::INIT_SPACE,
+      seeds = [
+        b"ticket_shard".as_ref(),
+        token_lottery.key().as_ref(),
+        shard.to_le_bytes().as_ref(),
+      ],
+      bump
     )]
-    pub destination_token_account: InterfaceAccount<'info, TokenAccount>,
-    
+    pub ticket_shard: Account<'info, TicketShard>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Same accounts as `BuyTicket`, except that the payment and counters go to
+// a shard and the lottery is only read, so purchases through different
+// shards don't wait for each other.
+#[derive(Accounts)]
+pub struct BuyTicketSharded<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: receives the ticket, can be any wallet
+    pub recipient: UncheckedAccount<'info>,
+
+    pub token_lottery: Box<Account<'info, TokenLottery>>,
 
     #[account(
       mut,
       seeds = [
-        b"metadata",
-        token_metadata_program.key().as_ref(),
-        ticket_mint.key().as_ref()
+        b"ticket_shard".as_ref(),
+        token_lottery.key().as_ref(),
+        ticket_shard.shard.to_le_bytes().as_ref(),
       ],
-      bump,
-      seeds::program = token_metadata_program.key(),
+      bump = ticket_shard.bump,
     )]
-    /// CHECK: This account will be initialized by the metaplex program
-    pub metadata: UncheckedAccount<'info>,
+    pub ticket_shard: Box<Account<'info, TicketShard>>,
 
     #[account(
-      mut,
+      init,
+      payer = payer,
+      space = 8 + Ticket::INIT_SPACE,
       seeds = [
-        b"metadata",
-        token_metadata_program.key().as_ref(),
+        b"ticket".as_ref(),
         ticket_mint.key().as_ref(),
-        b"edition"
       ],
-      bump,
-      seeds::program = token_metadata_program.key(),
+      bump
     )]
-    /// CHECK: This account will be initialized by the metaplex program
-    pub master_edition: UncheckedAccount<'info>,
+    pub ticket: Box<Account<'info, Ticket>>,
 
     #[account(
-      mut,
+      init,
+      payer = payer,
       seeds = [
-        b"metadata",
-        token_metadata_program.key().as_ref(),
-        collection_mint.key().as_ref()
+        b"ticket_mint".as_ref(),
+        buyer.key().as_ref(),
+        token_lottery.id.to_le_bytes().as_ref(),
+        ticket_shard.next_index().to_le_bytes().as_ref()
       ],
       bump,
-      seeds::program = token_metadata_program.key()
+      mint::authority = collection_mint,
+      mint::decimals = 0,
+      mint::freeze_authority = collection_mint,
+      mint::token_program = token_program
     )]
-    /// CHECK: This account will be initialized by the metaplex program
-    pub collection_metadata: UncheckedAccount<'info>,
+    pub ticket_mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
-      mut,
-      seeds = [
-        b"metadata",
-        token_metadata_program.key().as_ref(),
-        collection_mint.key().as_ref(),
-        b"edition"
-      ],
-      bump,
-      seeds::program = token_metadata_program.key(),
+      init,
+      payer = payer,
+      associated_token::mint = ticket_mint,
+      associated_token::authority = recipient,
+      associated_token::token_program = token_program,
     )]
+    pub destination_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // derivations are checked by the metaplex program, see `BuyTicket`
+    #[account(mut)]
     /// CHECK: This account will be initialized by the metaplex program
-    pub collection_master_edition: UncheckedAccount<'info>,
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: This account will be initialized by the metaplex program
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: checked by the metaplex program when verifying the ticket
+    pub collection_metadata: UncheckedAccount<'info>,
 
+    #[account(mut)]
+    /// CHECK: checked by the metaplex program when verifying the ticket
+    pub collection_master_edition: UncheckedAccount<'info>,
 
     #[account(
       mut,
       seeds = [
         b"collection_mint",
-        payer.key().as_ref(),
+        token_lottery.authority.as_ref(),
         token_lottery.id.to_le_bytes().as_ref(),
       ],
-      bump,
+      bump = token_lottery.cached_collection_mint_bump(),
     )]
-    pub collection_mint: InterfaceAccount<'info, Mint>,
+    pub collection_mint: Box<InterfaceAccount<'info, Mint>>,
 
+    // proof accounts for gated lotteries, see `PurchaseGate`
+    pub gate_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub gate_metadata: Option<Box<Account<'info, MetadataAccount>>>,
+
+    #[account(mut)]
+    pub referral: Option<Box<Account<'info, Referral>>>,
 
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
@@ -599,92 +3003,236 @@ pub struct BuyTicket<'info> {
     pub rent: Sysvar<'info, Rent>,
 }
 
-#[derive(Accounts)]
-pub struct CommitWinner<'info> {
-  #[account(mut)]
-  pub payer: Signer<'info>,
+impl<'info> BuyTicketSharded<'info> {
+  // `BuyTicket::purchase` with the pot, principal and counters kept on the
+  // shard until `finalize_shards`.
+  pub fn purchase(&mut self, bumps: &BuyTicketShardedBumps, slot: u64, max_price: u64) -> Result<()> {
+    check_gate(
+      &self.token_lottery,
+      &self.buyer.key(),
+      self.gate_token_account.as_deref().map(|account| &**account),
+      self.gate_metadata.as_deref().map(|account| &**account),
+    )?;
+    require!(self.ticket_shard.number_of_tickets < TICKETS_PER_SHARD, ErrorCode::ShardFull);
 
-  #[account(mut)]
-  pub token_lottery: Account<'info, TokenLottery>,
-  /// CHECK: This account will be initialized by the metaplex program
-  pub randomness_account_data: UncheckedAccount<'info>,
-  
-  pub system_program: Program<'info, System>,
-}
+    let index = self.ticket_shard.next_index();
+    let ticket_name = NAME.to_owned() + index.to_string().as_str();
 
+    // sharded lotteries are flat priced, the count the lottery holds doesn't matter
+    let price = self.token_lottery.current_price(slot)?;
+    require!(price <= max_price, ErrorCode::PriceAboveMax);
 
-#[derive(Accounts)]
-pub struct ChooseWinner<'info> {
-  #[account(mut)]
-  pub payer: Signer<'info>,
+    let referral_fee = pay_referral(
+      &self.token_lottery,
+      &self.buyer,
+      self.referral.as_deref_mut(),
+      &self.system_program,
+      price,
+    )?;
+    let pot_share = price.checked_sub(referral_fee).ok_or(ErrorCode::Overflow)?;
 
-  #[account(mut)]
-  pub token_lottery: Account<'info, TokenLottery>,
-  /// CHECK: This account will be initialized by the metaplex program
-  pub randomness_account_data: UncheckedAccount<'info>,
-  
-  pub system_program: Program<'info, System>,
+    system_program::transfer(
+      CpiContext::new(
+        self.system_program.to_account_info(),
+        system_program::Transfer {
+          from: self.buyer.to_account_info(),
+          to: self.ticket_shard.to_account_info(),
+        }
+      ),
+      pot_share,
+    )?;
+
+    let ticket_shard = &mut self.ticket_shard;
+    ticket_shard.pot_amount = ticket_shard.pot_amount.checked_add(pot_share).ok_or(ErrorCode::Overflow)?;
+    if self.token_lottery.is_no_loss {
+      ticket_shard.principal_amount = ticket_shard.principal_amount.checked_add(pot_share).ok_or(ErrorCode::Overflow)?;
+    }
+    ticket_shard.referral_total = ticket_shard.referral_total.checked_add(referral_fee).ok_or(ErrorCode::Overflow)?;
+
+    self.ticket.lottery = self.token_lottery.key();
+    self.ticket.mint = self.ticket_mint.key();
+    self.ticket.index = index;
+    self.ticket.price_paid = price;
+    self.ticket.referral_fee = referral_fee;
+    self.ticket.referrer = self.referral.as_ref().map_or(Pubkey::default(), |referral| referral.referrer);
+    self.ticket.bump = bumps.ticket;
+
+    let authority = self.token_lottery.authority;
+    let collection_mint_bump = self.token_lottery.cached_collection_mint_bump();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+      b"collection_mint".as_ref(),
+      authority.as_ref(),
+      &self.token_lottery.id.to_le_bytes(),
+      &[collection_mint_bump]
+    ]];
+
+    mint_ticket(
+      TicketMintAccounts {
+        payer: self.payer.to_account_info(),
+        ticket_mint: self.ticket_mint.to_account_info(),
+        destination_token_account: self.destination_token_account.to_account_info(),
+        recipient: self.recipient.to_account_info(),
+        metadata: self.metadata.to_account_info(),
+        master_edition: self.master_edition.to_account_info(),
+        collection_metadata: self.collection_metadata.to_account_info(),
+        collection_master_edition: self.collection_master_edition.to_account_info(),
+        collection_mint: self.collection_mint.to_account_info(),
+        token_program: self.token_program.to_account_info(),
+        system_program: self.system_program.to_account_info(),
+        token_metadata_program: self.token_metadata_program.to_account_info(),
+        rent: self.rent.to_account_info(),
+      },
+      ticket_name,
+      self.token_lottery.ticket_freeze,
+      signer_seeds,
+    )?;
+
+    emit!(TicketPurchased {
+      lottery: self.token_lottery.key(),
+      ticket_mint: self.ticket_mint.key(),
+      index,
+      buyer: self.buyer.key(),
+      recipient: self.recipient.key(),
+      price_paid: price,
+      referral_fee,
+      slot,
+    });
+
+    self.ticket_shard.number_of_tickets = self.ticket_shard.number_of_tickets.checked_add(1).ok_or(ErrorCode::Overflow)?;
+
+    Ok(())
+  }
 }
 
 #[derive(Accounts)]
-pub struct ClaimPrize<'info> {
+pub struct BurnTicket<'info> {
     #[account(mut)]
-    pub payer: Signer<'info>,
+    pub holder: Signer<'info>,
 
-    #[account(mut)]
     pub token_lottery: Account<'info, TokenLottery>,
 
     #[account(mut)]
     pub ticket_mint: InterfaceAccount<'info, Mint>,
 
     #[account(
-      associated_token::mint = ticket_mint,
-      associated_token::authority = payer,
-      associated_token::token_program = token_program,
+      mut,
+      close = holder,
+      constraint = ticket.lottery == token_lottery.key() @ ErrorCode::IncorrectTicket,
+      seeds = [
+        b"ticket".as_ref(),
+        ticket_mint.key().as_ref(),
+      ],
+      bump = ticket.bump,
     )]
-    pub destination_token_account: InterfaceAccount<'info, TokenAccount>,
-    
+    pub ticket: Account<'info, Ticket>,
 
     #[account(
-      seeds = [
-        b"metadata".as_ref(),
-        token_metadata_program.key().as_ref(),
-        ticket_mint.key().as_ref()
-      ],
-      bump,
-      seeds::program = token_metadata_program.key(),
+      mut,
+      token::mint = ticket_mint,
+      token::authority = holder,
+      token::token_program = token_program,
     )]
-    pub metadata: Account<'info, MetadataAccount>,
+    pub holder_token_account: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
       mut,
       seeds = [
-        b"metadata".as_ref(),
+        b"metadata",
         token_metadata_program.key().as_ref(),
-        collection_mint.key().as_ref()
+        ticket_mint.key().as_ref()
       ],
       bump,
-      seeds::program = token_metadata_program.key()
+      seeds::program = token_metadata_program.key(),
     )]
-    pub collection_metadata: Account<'info, MetadataAccount>,
+    pub metadata: Account<'info, MetadataAccount>,
 
+    #[account(mut)]
+    /// CHECK: checked by the metaplex program when burning the ticket
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: checked by the metaplex program when shrinking the collection
+    pub collection_metadata: UncheckedAccount<'info>,
 
     #[account(
-      mut,
       seeds = [
         b"collection_mint".as_ref(),
-        payer.key().as_ref(),
+        token_lottery.authority.as_ref(),
         token_lottery.id.to_le_bytes().as_ref(),
       ],
-      bump,
+      bump = token_lottery.cached_collection_mint_bump(),
     )]
     pub collection_mint: InterfaceAccount<'info, Mint>,
 
+    pub token_program: Interface<'info, TokenInterface>,
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeShards<'info> {
+    #[account(mut)]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    /// CHECK: receives the rent of the closed shards
+    #[account(mut, address = token_lottery.authority @ ErrorCode::NotAuthorized)]
+    pub authority: UncheckedAccount<'info>,
+}
+
+#[event]
+pub struct LotteryCreated{
+    pub lottery: Pubkey,
+    pub id: u64,
+    pub authority: Pubkey,
+    pub lottery_start: u64,
+    pub lottery_end: u64,
+    pub price: u64,
+    pub min_tickets: u64,
+}
+
+// emitted for bought and promotional tickets, the latter with a zero price
+#[event]
+pub struct TicketPurchased{
+    pub lottery: Pubkey,
+    pub ticket_mint: Pubkey,
+    pub index: u64,
+    pub buyer: Pubkey,
+    pub recipient: Pubkey,
+    pub price_paid: u64,
+    pub referral_fee: u64,
+    pub slot: u64,
+}
 
-    pub token_program: Interface<'info, TokenInterface>,
-    pub system_program: Program<'info, System>,
-    pub token_metadata_program: Program<'info, Metadata>,
-    
+#[event]
+pub struct WinnerChosen{
+    pub lottery: Pubkey,
+    pub winner: u64,
+    pub number_of_tickets: u64,
+    pub randomness_account: Pubkey,
+    pub revealed_value: [u8; 32],
+    pub slot: u64,
+}
+
+// emitted once, when the pot is paid out
+#[event]
+pub struct PrizeClaimed{
+    pub lottery: Pubkey,
+    pub ticket_mint: Pubkey,
+    pub winner: Pubkey,
+    pub amount: u64,
+}
+
+// emitted by claim_refund and withdraw_principal
+#[event]
+pub struct TicketRefunded{
+    pub lottery: Pubkey,
+    pub ticket_mint: Pubkey,
+    pub holder: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct LotteryCancelled{
+    pub lottery: Pubkey,
 }
 
 #[error_code]
@@ -707,6 +3255,84 @@ pub enum ErrorCode{
   NotVerifiedTicket,
   #[msg("Incorrect ticket")]
   IncorrectTicket,
+  #[msg("Amount must be greater than zero")]
+  InvalidAmount,
+  #[msg("Lottery is cancelled")]
+  LotteryCancelled,
+  #[msg("Lottery is not refundable")]
+  LotteryNotRefundable,
+  #[msg("Arithmetic overflow")]
+  Overflow,
+  #[msg("Not enough tickets sold")]
+  BelowMinimumTickets,
+  #[msg("Lottery has already started")]
+  LotteryAlreadyStarted,
+  #[msg("Incorrect prize accounts")]
+  IncorrectPrizeAccounts,
+  #[msg("Invalid pricing")]
+  InvalidPricing,
+  #[msg("Ticket price is above the maximum price")]
+  PriceAboveMax,
+  #[msg("Only allowlisted buyers can purchase during the presale")]
+  PresaleOnly,
+  #[msg("Invalid presale window")]
+  InvalidPresaleWindow,
+  #[msg("Buyer is not on the allowlist")]
+  NotAllowlisted,
+  #[msg("Allowlist allowance exceeded")]
+  AllowanceExceeded,
+  #[msg("Purchase gate not satisfied")]
+  GateNotSatisfied,
+  #[msg("Invalid referral share")]
+  InvalidReferralShare,
+  #[msg("Referral account belongs to another lottery")]
+  IncorrectReferral,
+  #[msg("Buyers cannot refer themselves")]
+  SelfReferral,
+  #[msg("Promotional ticket cap reached")]
+  PromoTicketCapReached,
+  #[msg("Entry weights account missing or invalid")]
+  EntryWeightsRequired,
+  #[msg("Too many weighted entries")]
+  TooManyEntries,
+  #[msg("Lottery is not a no-loss lottery")]
+  NotNoLossLottery,
+  #[msg("Lottery account is not a v1 account")]
+  AlreadyMigrated,
+  #[msg("Lottery still holds funds or prizes")]
+  LotteryNotSettled,
+  #[msg("Randomness account is not a Switchboard randomness account")]
+  InvalidRandomnessAccount,
+  #[msg("No tickets to draw a winner from")]
+  NoTickets,
+  #[msg("Account holds fewer lamports than the payout")]
+  InsufficientFunds,
+  #[msg("Shard account missing, out of order or of another lottery")]
+  IncorrectShard,
+  #[msg("Too many ticket shards")]
+  TooManyShards,
+  #[msg("Sharded sales need flat pricing and an unweighted lottery")]
+  ShardingUnsupported,
+  #[msg("Ticket range of the shard is full")]
+  ShardFull,
+  #[msg("Shard sales have not been finalized")]
+  ShardsNotFinalized,
+  #[msg("Shard sales are already finalized")]
+  ShardsFinalized,
+  #[msg("Ticket still has principal to withdraw")]
+  PrincipalOutstanding,
+  #[msg("Recipient must sign to receive a frozen ticket")]
+  RecipientMustSign,
+  #[msg("Recipient is not the holder or the wallet it designated")]
+  IncorrectRecipient,
+  #[msg("Randomness already committed")]
+  RandomnessCommitted,
+  #[msg("Prize of this mint was deposited by another wallet")]
+  IncorrectDepositor,
+  #[msg("No-loss lotteries can't pay referral fees")]
+  NoLossReferrals,
+  #[msg("Committed randomness can still be revealed")]
+  RandomnessNotExpired,
 
 
 }
@@ -716,6 +3342,182 @@ pub enum ErrorCode{
 #[account]
 #[derive(InitSpace)]
 pub struct TokenLottery{
+    pub version: u8,
+    pub id: u64,
+    pub bump: u8,
+    pub winner: u64,
+    pub is_winner_chosen: bool,
+    pub lottery_start: u64,
+    pub lottery_end: u64,
+    pub price: u64,
+    pub number_of_tickets: u64,
+    pub lottery_pot_amount: u64,
+    pub authority: Pubkey,
+    pub randomness_account: Pubkey,
+    pub sponsor_amount: u64,
+    pub is_cancelled: bool,
+    pub min_tickets: u64,
+    pub prize_count: u32,
+    #[max_len(MAX_PRICE_TIERS)]
+    pub price_tiers: Vec<PriceTier>,
+    pub bonding_curve: BondingCurve,
+    pub allowlist_root: [u8; 32],
+    pub presale_start: u64,
+    pub presale_end: u64,
+    pub purchase_gate: PurchaseGate,
+    pub referral_bps: u16,
+    pub referral_total: u64,
+    pub promo_tickets: u64,
+    pub max_promo_tickets: Option<u64>,
+    pub is_weighted: bool,
+    pub is_no_loss: bool,
+    pub principal_amount: u64,
+    pub is_prize_claimed: bool,
+    // bump of the collection mint PDA, 0 for lotteries created before it was cached
+    pub collection_mint_bump: u8,
+    // number of `TicketShard`s selling tickets next to the lottery
+    pub shard_count: u8,
+    pub is_shards_finalized: bool,
+    // tickets sold by each shard, set by `finalize_shards`
+    pub shard_tickets: [u64; MAX_TICKET_SHARDS],
+    pub ticket_freeze: TicketFreeze,
+    // tickets sold before `migrate_lottery`, which have no `Ticket` receipt
+    // until `backfill_ticket` creates it
+    pub v1_tickets: u64,
+    // room for new fields without growing the account
+    pub reserved: [u8; 52],
+}
+
+impl TokenLottery {
+    // Reads a `TokenLotteryV1` account, discriminator included, and returns
+    // it in the current layout.
+    pub fn from_v1_data(data: &[u8]) -> Result<Self> {
+        require!(data.len() == 8 + TokenLotteryV1::INIT_SPACE, ErrorCode::AlreadyMigrated);
+        require!(data[..8] == TokenLottery::DISCRIMINATOR, ErrorCode::AlreadyMigrated);
+
+        let v1 = TokenLotteryV1::deserialize(&mut &data[8..])?;
+
+        Ok(Self {
+            version: TOKEN_LOTTERY_VERSION,
+            id: v1.id,
+            bump: v1.bump,
+            winner: v1.winner,
+            is_winner_chosen: v1.is_winner_chosen,
+            lottery_start: v1.lottery_start,
+            lottery_end: v1.lottery_end,
+            price: v1.price,
+            number_of_tickets: v1.number_of_tickets,
+            lottery_pot_amount: v1.lottery_pot_amount,
+            authority: v1.authority,
+            randomness_account: v1.randomness_account,
+            sponsor_amount: 0,
+            is_cancelled: false,
+            min_tickets: 0,
+            prize_count: 0,
+            price_tiers: Vec::new(),
+            bonding_curve: BondingCurve::Flat,
+            allowlist_root: [0; 32],
+            presale_start: 0,
+            presale_end: 0,
+            purchase_gate: PurchaseGate::None,
+            referral_bps: 0,
+            referral_total: 0,
+            promo_tickets: 0,
+            max_promo_tickets: None,
+            is_weighted: false,
+            is_no_loss: false,
+            principal_amount: 0,
+            is_prize_claimed: false,
+            collection_mint_bump: collection_mint_address(&v1.authority, v1.id).1,
+            shard_count: 0,
+            is_shards_finalized: false,
+            shard_tickets: [0; MAX_TICKET_SHARDS],
+            ticket_freeze: TicketFreeze::Transferable,
+            v1_tickets: v1.number_of_tickets,
+            reserved: [0; 52],
+        })
+    }
+
+    // Bump used to sign for the collection mint, derived when not cached.
+    pub fn cached_collection_mint_bump(&self) -> u8 {
+        if self.collection_mint_bump != 0 {
+            return self.collection_mint_bump;
+        }
+        collection_mint_address(&self.authority, self.id).1
+    }
+
+    // A lottery that was cancelled, or that closed without selling
+    // `min_tickets`, pays everything back instead of drawing.
+    pub fn is_refundable(&self, slot: u64) -> bool {
+        self.is_cancelled
+            || (slot > self.lottery_end && !self.is_sharding_pending() && self.number_of_tickets < self.min_tickets)
+    }
+
+    // Settings are frozen once the first ticket is sold. Shard sales don't
+    // touch the lottery, so creating the first shard freezes them as well.
+    pub fn is_configurable(&self) -> bool {
+        self.number_of_tickets == 0 && self.shard_count == 0
+    }
+
+    // Shard sales have not been added to the ticket count and pot yet.
+    pub fn is_sharding_pending(&self) -> bool {
+        self.shard_count > 0 && !self.is_shards_finalized
+    }
+
+    // Index of the `ordinal`-th ticket when counting the lottery's own
+    // tickets first and then those of every shard in order.
+    pub fn ticket_index_at(&self, ordinal: u64) -> Option<u64> {
+        let sharded = self.shard_tickets.iter().try_fold(0u64, |sum, count| sum.checked_add(*count))?;
+        let own = self.number_of_tickets.checked_sub(sharded)?;
+        if ordinal < own {
+            return Some(ordinal);
+        }
+
+        let mut remaining = ordinal - own;
+        for (shard, count) in self.shard_tickets.iter().enumerate().take(self.shard_count as usize) {
+            if remaining < *count {
+                return TicketShard::first_index(shard as u8).checked_add(remaining);
+            }
+            remaining -= count;
+        }
+        None
+    }
+
+    // Share of `price` paid to the referrer of a purchase.
+    pub fn referral_fee(&self, price: u64) -> Result<u64> {
+        u64::try_from(price as u128 * self.referral_bps as u128 / BPS_DENOMINATOR as u128)
+            .map_err(|_| ErrorCode::Overflow.into())
+    }
+
+    // No-loss tickets are fully redeemable, so none of their price can go to
+    // a referrer.
+    pub fn check_referrals(&self) -> Result<()> {
+        require!(!self.is_no_loss || self.referral_bps == 0, ErrorCode::NoLossReferrals);
+        Ok(())
+    }
+
+    // An all zero root means the lottery has no presale.
+    pub fn is_presale(&self, slot: u64) -> bool {
+        self.allowlist_root != [0; 32] && slot >= self.presale_start && slot < self.presale_end
+    }
+
+    // Price of the next ticket: the first tier whose `until` slot has not
+    // passed (or `price` once all tiers are over), moved along the bonding
+    // curve by the number of tickets already sold.
+    pub fn current_price(&self, slot: u64) -> Result<u64> {
+        let base = self.price_tiers
+            .iter()
+            .find(|tier| slot < tier.until)
+            .map_or(self.price, |tier| tier.price);
+
+        self.bonding_curve.price_at(base, self.number_of_tickets)
+    }
+}
+
+// Layout of `TokenLottery` accounts created before the version field,
+// only used by `migrate_lottery`.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace)]
+pub struct TokenLotteryV1{
     pub id: u64,
     pub bump: u8,
     pub winner: u64,
@@ -726,5 +3528,831 @@ pub struct TokenLottery{
     pub number_of_tickets: u64,
     pub lottery_pot_amount: u64,
     pub authority: Pubkey,
-    pub randomness_account: Pubkey
-}
\ No newline at end of file
+    pub randomness_account: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct PriceTier{
+    pub until: u64,
+    pub price: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub enum PurchaseGate{
+    #[default]
+    None,
+    // buyer holds at least `min_amount` of `mint`
+    TokenBalance { mint: Pubkey, min_amount: u64 },
+    // buyer holds an NFT of the verified metaplex collection `collection_mint`
+    Collection { collection_mint: Pubkey },
+}
+
+// When ticket transfers are frozen. Freezing never decides who can claim.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, InitSpace)]
+pub enum TicketFreeze{
+    #[default]
+    Transferable,
+    // tickets are frozen as soon as they are minted
+    Soulbound,
+}
+
+impl TicketFreeze {
+    // Whether a ticket is frozen when it is minted. The recipient has to sign
+    // to delegate the ticket to the collection mint, and the ticket is frozen
+    // in the same instruction so the delegate can't be revoked first.
+    pub fn freezes_at_mint(&self, recipient_is_signer: bool) -> Result<bool> {
+        match self {
+            TicketFreeze::Transferable => Ok(false),
+            TicketFreeze::Soulbound => {
+                require!(recipient_is_signer, ErrorCode::RecipientMustSign);
+                Ok(true)
+            }
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub enum BondingCurve{
+    #[default]
+    Flat,
+    // every sold ticket adds `step` lamports
+    Linear { step: u64 },
+    // every sold ticket adds `growth_bps` basis points, compounded
+    Exponential { growth_bps: u16 },
+}
+
+impl BondingCurve {
+    pub fn price_at(&self, base: u64, sold: u64) -> Result<u64> {
+        let price = match *self {
+            BondingCurve::Flat => Some(base),
+            BondingCurve::Linear { step } => step
+                .checked_mul(sold)
+                .and_then(|increase| base.checked_add(increase)),
+            BondingCurve::Exponential { growth_bps } => {
+                // fixed point exponentiation by squaring
+                const ONE: u128 = 1_000_000_000_000;
+                let mut factor = ONE * (BPS_DENOMINATOR + growth_bps as u64) as u128 / BPS_DENOMINATOR as u128;
+                let mut result = ONE;
+                let mut exponent = sold;
+                let mut overflow = false;
+                while exponent > 0 && !overflow {
+                    if exponent & 1 == 1 {
+                        match result.checked_mul(factor) {
+                            Some(value) => result = value / ONE,
+                            None => overflow = true,
+                        }
+                    }
+                    exponent >>= 1;
+                    if exponent > 0 {
+                        match factor.checked_mul(factor) {
+                            Some(value) => factor = value / ONE,
+                            None => overflow = true,
+                        }
+                    }
+                }
+                if overflow {
+                    None
+                } else {
+                    (base as u128)
+                        .checked_mul(result)
+                        .map(|value| value / ONE)
+                        .and_then(|value| u64::try_from(value).ok())
+                }
+            }
+        };
+
+        price.ok_or(ErrorCode::Overflow.into())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Registry{
+    pub lottery_count: u64,
+    pub bump: u8,
+}
+
+// Number of lotteries created by `authority`, each one has an
+// `AuthorityLottery` entry at indexes `0..lottery_count`.
+#[account]
+#[derive(InitSpace)]
+pub struct AuthorityIndex{
+    pub authority: Pubkey,
+    pub lottery_count: u64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct AuthorityLottery{
+    pub authority: Pubkey,
+    pub index: u64,
+    pub id: u64,
+    pub lottery: Pubkey,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Sponsorship{
+    pub lottery: Pubkey,
+    pub sponsor: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+}
+
+// Cumulative entry weights stored as a Fenwick tree, entry `i` lives at
+// `tree[i]` using 0-based indexing, so updates and draws are O(log n).
+#[account(zero_copy)]
+pub struct EntryWeights{
+    pub lottery: Pubkey,
+    pub len: u64,
+    pub total_weight: u64,
+    pub tree: [u64; MAX_WEIGHTED_ENTRIES as usize],
+}
+
+impl EntryWeights {
+    // Appends the next ticket of `lottery` with the default weight, refusing
+    // another lottery's tree or one that is out of step with the ticket count.
+    pub fn record(&mut self, lottery: Pubkey, number_of_tickets: u64) -> Result<()> {
+        require_keys_eq!(self.lottery, lottery, ErrorCode::EntryWeightsRequired);
+        require!(self.len == number_of_tickets, ErrorCode::EntryWeightsRequired);
+        self.push(DEFAULT_ENTRY_WEIGHT)
+    }
+
+    pub fn push(&mut self, weight: u64) -> Result<()> {
+        require!(self.len < MAX_WEIGHTED_ENTRIES, ErrorCode::TooManyEntries);
+        self.len += 1;
+        self.add(self.len - 1, weight)
+    }
+
+    pub fn set(&mut self, index: u64, weight: u64) -> Result<()> {
+        let current = self.weight_of(index);
+        if weight >= current {
+            self.add(index, weight - current)
+        } else {
+            self.sub(index, current - weight)
+        }
+    }
+
+    pub fn weight_of(&self, index: u64) -> u64 {
+        self.prefix_sum(index + 1) - self.prefix_sum(index)
+    }
+
+    // Sum of the weights of the first `count` entries.
+    pub fn prefix_sum(&self, count: u64) -> u64 {
+        let mut sum = 0;
+        let mut i = count;
+        while i > 0 {
+            sum += self.tree[(i - 1) as usize];
+            i &= i - 1;
+        }
+        sum
+    }
+
+    // Index of the entry covering `value`, for `value` in `[0, total_weight)`.
+    pub fn find(&self, value: u64) -> u64 {
+        let mut position = 0;
+        let mut remaining = value;
+        let mut step = MAX_WEIGHTED_ENTRIES;
+        while step > 0 {
+            let next = position + step;
+            if next <= self.len && self.tree[(next - 1) as usize] <= remaining {
+                remaining -= self.tree[(next - 1) as usize];
+                position = next;
+            }
+            step >>= 1;
+        }
+        position
+    }
+
+    fn add(&mut self, index: u64, delta: u64) -> Result<()> {
+        self.total_weight = self.total_weight.checked_add(delta).ok_or(ErrorCode::Overflow)?;
+        let mut i = index + 1;
+        while i <= MAX_WEIGHTED_ENTRIES {
+            self.tree[(i - 1) as usize] += delta;
+            i += i & i.wrapping_neg();
+        }
+        Ok(())
+    }
+
+    fn sub(&mut self, index: u64, delta: u64) -> Result<()> {
+        self.total_weight = self.total_weight.checked_sub(delta).ok_or(ErrorCode::Overflow)?;
+        let mut i = index + 1;
+        while i <= MAX_WEIGHTED_ENTRIES {
+            self.tree[(i - 1) as usize] -= delta;
+            i += i & i.wrapping_neg();
+        }
+        Ok(())
+    }
+}
+
+// Audit record of a draw, kept after the lottery account is closed.
+#[account]
+#[derive(InitSpace)]
+pub struct DrawResult{
+    pub lottery: Pubkey,
+    pub randomness_account: Pubkey,
+    pub revealed_value: [u8; 32],
+    pub commit_slot: u64,
+    pub reveal_slot: u64,
+    pub number_of_tickets: u64,
+    pub algorithm_version: u8,
+    #[max_len(MAX_WINNERS)]
+    pub winning_indices: Vec<u64>,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Ticket{
+    pub lottery: Pubkey,
+    pub mint: Pubkey,
+    pub index: u64,
+    pub price_paid: u64,
+    pub referral_fee: u64,
+    // paid `referral_fee`, the default key when the ticket had no referrer
+    pub referrer: Pubkey,
+    pub bump: u8,
+}
+
+impl Ticket {
+    // What the ticket put into the pot, the referral fee went to the referrer.
+    pub fn principal(&self) -> u64 {
+        self.price_paid.saturating_sub(self.referral_fee)
+    }
+}
+
+impl Referral {
+    // Takes the referral fee of a refunded ticket back out of the referrer's
+    // unclaimed rewards and returns how much to pay back from this account.
+    pub fn take_back_fee(&mut self, ticket: &Ticket) -> Result<u64> {
+        require!(self.lottery == ticket.lottery && self.referrer == ticket.referrer, ErrorCode::IncorrectReferral);
+        self.accrued = self.accrued.checked_sub(ticket.referral_fee).ok_or(ErrorCode::Overflow)?;
+        Ok(ticket.referral_fee)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Referral{
+    pub lottery: Pubkey,
+    pub referrer: Pubkey,
+    pub accrued: u64,
+    pub bump: u8,
+}
+
+// Wallet `holder` wants the prize of `ticket_mint` paid to by
+// `claim_prize_for`, ignored once the ticket changes hands.
+#[account]
+#[derive(InitSpace)]
+pub struct PrizeRecipient{
+    pub ticket_mint: Pubkey,
+    pub holder: Pubkey,
+    pub recipient: Pubkey,
+    pub bump: u8,
+}
+
+impl PrizeRecipient {
+    // The designation stored in `account`, `None` while nobody designated a
+    // recipient for the ticket.
+    pub fn designated(account: &AccountInfo) -> Result<Option<Self>> {
+        if account.owner != &crate::ID || account.data_is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self::try_deserialize(&mut &account.data.borrow()[..])?))
+    }
+}
+
+// Wallet the authority allowed to escrow prizes with `deposit_prize`.
+#[account]
+#[derive(InitSpace)]
+pub struct PrizeDepositor{
+    pub lottery: Pubkey,
+    pub depositor: Pubkey,
+    pub bump: u8,
+}
+
+impl PrizeDepositor {
+    // Only the program can create the account at the approval's address, so
+    // an approval exists as long as the account does.
+    pub fn is_approved(account: &AccountInfo) -> bool {
+        account.owner == &crate::ID && !account.data_is_empty()
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct AllowlistUsage{
+    pub lottery: Pubkey,
+    pub buyer: Pubkey,
+    pub purchased: u64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Prize{
+    pub lottery: Pubkey,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub depositor: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+}
+
+// Sells the ticket indices from `first_index(shard)` without locking the
+// lottery. `finalize_shards` moves its counters and lamports to the lottery
+// and closes it.
+#[account]
+#[derive(InitSpace)]
+pub struct TicketShard{
+    pub lottery: Pubkey,
+    pub shard: u8,
+    pub number_of_tickets: u64,
+    pub pot_amount: u64,
+    pub principal_amount: u64,
+    pub referral_total: u64,
+    pub bump: u8,
+}
+
+impl TicketShard {
+    pub fn first_index(shard: u8) -> u64 {
+        (shard as u64 + 1) * TICKETS_PER_SHARD
+    }
+
+    // Index of the next ticket the shard sells.
+    pub fn next_index(&self) -> u64 {
+        Self::first_index(self.shard) + self.number_of_tickets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v1_lottery() -> TokenLotteryV1 {
+        TokenLotteryV1 {
+            id: 7,
+            bump: 254,
+            winner: 3,
+            is_winner_chosen: true,
+            lottery_start: 100,
+            lottery_end: 200,
+            price: 10_000,
+            number_of_tickets: 5,
+            lottery_pot_amount: 50_000,
+            authority: Pubkey::new_unique(),
+            randomness_account: Pubkey::new_unique(),
+        }
+    }
+
+    // Account data as written by the deployed program, field by field in
+    // its declaration order.
+    fn v1_blob(v1: &TokenLotteryV1) -> Vec<u8> {
+        let mut blob = TokenLottery::DISCRIMINATOR.to_vec();
+        blob.extend_from_slice(&v1.id.to_le_bytes());
+        blob.push(v1.bump);
+        blob.extend_from_slice(&v1.winner.to_le_bytes());
+        blob.push(v1.is_winner_chosen as u8);
+        blob.extend_from_slice(&v1.lottery_start.to_le_bytes());
+        blob.extend_from_slice(&v1.lottery_end.to_le_bytes());
+        blob.extend_from_slice(&v1.price.to_le_bytes());
+        blob.extend_from_slice(&v1.number_of_tickets.to_le_bytes());
+        blob.extend_from_slice(&v1.lottery_pot_amount.to_le_bytes());
+        blob.extend_from_slice(v1.authority.as_ref());
+        blob.extend_from_slice(v1.randomness_account.as_ref());
+        blob
+    }
+
+    #[test]
+    fn migrates_v1_account_keeping_all_fields() {
+        let v1 = v1_lottery();
+        let blob = v1_blob(&v1);
+        assert_eq!(blob.len(), 8 + TokenLotteryV1::INIT_SPACE);
+
+        let migrated = TokenLottery::from_v1_data(&blob).unwrap();
+        assert_eq!(migrated.version, TOKEN_LOTTERY_VERSION);
+        assert_eq!(migrated.id, v1.id);
+        assert_eq!(migrated.bump, v1.bump);
+        assert_eq!(migrated.winner, v1.winner);
+        assert!(migrated.is_winner_chosen);
+        assert_eq!(migrated.lottery_start, v1.lottery_start);
+        assert_eq!(migrated.lottery_end, v1.lottery_end);
+        assert_eq!(migrated.price, v1.price);
+        assert_eq!(migrated.number_of_tickets, v1.number_of_tickets);
+        assert_eq!(migrated.lottery_pot_amount, v1.lottery_pot_amount);
+        assert_eq!(migrated.authority, v1.authority);
+        assert_eq!(migrated.randomness_account, v1.randomness_account);
+
+        // fields added since start out as on a freshly created lottery
+        assert_eq!(migrated.min_tickets, 0);
+        assert!(migrated.price_tiers.is_empty());
+        assert_eq!(migrated.allowlist_root, [0; 32]);
+        assert!(migrated.max_promo_tickets.is_none());
+        assert!(!migrated.is_cancelled && !migrated.is_weighted && !migrated.is_no_loss);
+        assert!(migrated.ticket_freeze == TicketFreeze::Transferable);
+
+        // the v1 fields are serialized unchanged right after the version byte
+        let mut account = vec![0u8; 8 + TokenLottery::INIT_SPACE];
+        migrated.try_serialize(&mut &mut account[..]).unwrap();
+        assert_eq!(account[..8], TokenLottery::DISCRIMINATOR);
+        assert_eq!(account[8], TOKEN_LOTTERY_VERSION);
+        assert_eq!(account[9..9 + TokenLotteryV1::INIT_SPACE], blob[8..]);
+
+        // the collection mint bump is cached, and derived again when missing
+        let expected = Pubkey::find_program_address(&[b"collection_mint", v1.authority.as_ref(), &v1.id.to_le_bytes()], &crate::ID);
+        assert_eq!(migrated.collection_mint_bump, expected.1);
+        let mut uncached = migrated.clone();
+        uncached.collection_mint_bump = 0;
+        assert_eq!(uncached.cached_collection_mint_bump(), expected.1);
+
+        let reloaded = TokenLottery::try_deserialize(&mut &account[..]).unwrap();
+        assert_eq!(reloaded.try_to_vec().unwrap(), migrated.try_to_vec().unwrap());
+    }
+
+    #[test]
+    fn rejects_already_migrated_account() {
+        let migrated = TokenLottery::from_v1_data(&v1_blob(&v1_lottery())).unwrap();
+        let mut account = vec![0u8; 8 + TokenLottery::INIT_SPACE];
+        migrated.try_serialize(&mut &mut account[..]).unwrap();
+
+        assert!(TokenLottery::from_v1_data(&account).is_err());
+    }
+
+    #[test]
+    fn backfills_v1_receipts_so_migrated_winners_can_claim() {
+        let v1 = v1_lottery();
+        let lottery = TokenLottery::from_v1_data(&v1_blob(&v1)).unwrap();
+        assert_eq!(lottery.v1_tickets, v1.number_of_tickets);
+
+        // metaplex pads ticket names with zero bytes
+        let ticket_name = |index: u64| format!("{NAME}{index}{}", "\u{0}".repeat(4));
+        let key = Pubkey::new_unique();
+        let collection_mint = collection_mint_address(&v1.authority, v1.id).0;
+        let ticket_mint = Pubkey::new_unique();
+        let winning = named_metadata(ticket_mint, &ticket_name(v1.winner), Some((collection_mint, true)));
+        let ticket = backfilled_ticket(&lottery, key, &winning, collection_mint, 255).unwrap();
+        assert_eq!((ticket.lottery, ticket.mint, ticket.index), (key, ticket_mint, v1.winner));
+        assert_eq!(ticket.principal(), v1.price);
+
+        // tickets sold after the migration already have a receipt, and
+        // items of another collection aren't tickets of this lottery
+        let later = named_metadata(Pubkey::new_unique(), &ticket_name(v1.number_of_tickets), Some((collection_mint, true)));
+        assert!(backfilled_ticket(&lottery, key, &later, collection_mint, 255).is_err());
+        assert!(backfilled_ticket(&lottery, key, &winning, Pubkey::new_unique(), 255).is_err());
+
+        // with the receipt, claim_prize pays the winner the whole v1 pot
+        let holder = gate_token_account(ticket_mint, Pubkey::new_unique(), 1);
+        check_winning_ticket(&lottery, &winning, &collection_mint, &holder).unwrap();
+
+        let mut data = vec![0u8; 8 + TokenLottery::INIT_SPACE];
+        lottery.try_serialize(&mut &mut data[..]).unwrap();
+        let rent = 1_000_000;
+        let (mut lamports, mut payee_lamports) = (rent + v1.lottery_pot_amount, 0);
+        let mut payee_data = [];
+        let payee_key = Pubkey::new_unique();
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &crate::ID, false, 0);
+        let payee = AccountInfo::new(&payee_key, false, true, &mut payee_lamports, &mut payee_data, &system_program::ID, false, 0);
+        let mut token_lottery = Account::<TokenLottery>::try_from(&info).unwrap();
+
+        pay_pot(&mut token_lottery, &ticket, &payee).unwrap();
+        assert_eq!(payee.lamports(), v1.lottery_pot_amount);
+        assert_eq!(info.lamports(), rent);
+        assert!(token_lottery.is_prize_claimed);
+        assert_eq!(token_lottery.lottery_pot_amount, 0);
+    }
+
+    #[test]
+    fn freezes_soulbound_tickets_only_for_signing_recipients() {
+        assert!(!TicketFreeze::Transferable.freezes_at_mint(false).unwrap());
+        assert!(!TicketFreeze::Transferable.freezes_at_mint(true).unwrap());
+
+        // naming a recipient that doesn't sign can't turn a soulbound ticket
+        // into a transferable one
+        assert!(TicketFreeze::Soulbound.freezes_at_mint(false).is_err());
+        // and a signed mint freezes the ticket before its holder could
+        // revoke the delegate or move it to another token account
+        assert!(TicketFreeze::Soulbound.freezes_at_mint(true).unwrap());
+    }
+
+    #[test]
+    fn maps_draw_ordinals_to_shard_ticket_indices() {
+        let mut lottery = TokenLottery::from_v1_data(&v1_blob(&v1_lottery())).unwrap();
+        // 2 own tickets, then 3 sold by shard 0, none by shard 1 and 1 by shard 2
+        lottery.number_of_tickets = 6;
+        lottery.shard_count = 3;
+        lottery.shard_tickets[..3].copy_from_slice(&[3, 0, 1]);
+
+        let indices: Vec<_> = (0..7).map(|ordinal| lottery.ticket_index_at(ordinal)).collect();
+        assert_eq!(
+            indices,
+            [
+                Some(0),
+                Some(1),
+                Some(TICKETS_PER_SHARD),
+                Some(TICKETS_PER_SHARD + 1),
+                Some(TICKETS_PER_SHARD + 2),
+                Some(3 * TICKETS_PER_SHARD),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn expires_randomness_only_when_unrevealed_past_the_window() {
+        let mut randomness: RandomnessAccountData = bytemuck::Zeroable::zeroed();
+        randomness.seed_slot = 1_000;
+        let expiry = 1_000 + REVEAL_WINDOW_SLOTS;
+
+        assert!(!is_randomness_expired(&randomness, expiry));
+        assert!(is_randomness_expired(&randomness, expiry + 1));
+        // a revealed value is known, committing again would let it be rerolled
+        randomness.reveal_slot = 1_010;
+        assert!(!is_randomness_expired(&randomness, expiry + 1));
+    }
+
+    #[test]
+    fn reads_prize_recipient_designations() {
+        let designation = PrizeRecipient {
+            ticket_mint: Pubkey::new_unique(),
+            holder: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            bump: 255,
+        };
+        let mut data = vec![0u8; 8 + PrizeRecipient::INIT_SPACE];
+        designation.try_serialize(&mut &mut data[..]).unwrap();
+        let key = Pubkey::new_unique();
+        let (mut lamports, mut other_lamports, mut empty_lamports) = (1, 1, 0);
+        let mut other_data = data.clone();
+        let mut empty_data = [];
+
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &crate::ID, false, 0);
+        let read = PrizeRecipient::designated(&account).unwrap().unwrap();
+        assert_eq!(read.holder, designation.holder);
+        assert_eq!(read.recipient, designation.recipient);
+
+        // the same bytes under another owner, and an account never created
+        let system = system_program::ID;
+        let other = AccountInfo::new(&key, false, false, &mut other_lamports, &mut other_data, &system, false, 0);
+        assert!(PrizeRecipient::designated(&other).unwrap().is_none());
+        let empty = AccountInfo::new(&key, false, false, &mut empty_lamports, &mut empty_data, &system, false, 0);
+        assert!(PrizeRecipient::designated(&empty).unwrap().is_none());
+    }
+
+    fn entry_weights(weights: &[u64]) -> EntryWeights {
+        let mut entry_weights = EntryWeights {
+            lottery: Pubkey::new_unique(),
+            len: 0,
+            total_weight: 0,
+            tree: [0; MAX_WEIGHTED_ENTRIES as usize],
+        };
+        for weight in weights {
+            entry_weights.push(*weight).unwrap();
+        }
+        entry_weights
+    }
+
+    #[test]
+    fn pushes_and_sets_entry_weights() {
+        let mut entry_weights = entry_weights(&[3, 0, 5, 2]);
+        assert_eq!(entry_weights.len, 4);
+        assert_eq!(entry_weights.total_weight, 10);
+        assert_eq!((0..4).map(|index| entry_weights.weight_of(index)).collect::<Vec<_>>(), [3, 0, 5, 2]);
+        assert_eq!(entry_weights.prefix_sum(3), 8);
+
+        // raising and lowering a weight moves the total and leaves the others alone
+        entry_weights.set(1, 4).unwrap();
+        entry_weights.set(2, 1).unwrap();
+        assert_eq!(entry_weights.total_weight, 10);
+        assert_eq!((0..4).map(|index| entry_weights.weight_of(index)).collect::<Vec<_>>(), [3, 4, 1, 2]);
+
+        entry_weights.set(0, u64::MAX - 7).unwrap();
+        assert_eq!(entry_weights.total_weight, u64::MAX);
+        assert!(entry_weights.set(3, 3).is_err());
+    }
+
+    #[test]
+    fn records_entries_only_into_the_lottery_tree() {
+        let mut entry_weights = entry_weights(&[1, 1]);
+        let lottery = entry_weights.lottery;
+
+        // a foreign tree with a matching length is refused and left untouched
+        assert!(entry_weights.record(Pubkey::new_unique(), 2).is_err());
+        assert!(entry_weights.record(lottery, 1).is_err());
+        assert_eq!(entry_weights.len, 2);
+
+        entry_weights.record(lottery, 2).unwrap();
+        assert_eq!(entry_weights.len, 3);
+        assert_eq!(entry_weights.weight_of(2), DEFAULT_ENTRY_WEIGHT);
+    }
+
+    #[test]
+    fn rejects_entries_past_the_maximum() {
+        let mut entry_weights = entry_weights(&[1; MAX_WEIGHTED_ENTRIES as usize]);
+        assert_eq!(entry_weights.total_weight, MAX_WEIGHTED_ENTRIES);
+        assert!(entry_weights.push(1).is_err());
+        assert_eq!(entry_weights.find(MAX_WEIGHTED_ENTRIES - 1), MAX_WEIGHTED_ENTRIES - 1);
+    }
+
+    #[test]
+    fn finds_the_entry_covering_a_weight() {
+        // entry 1 has no weight and can never be drawn
+        let entry_weights = entry_weights(&[3, 0, 5, 2]);
+        let found: Vec<_> = (0..10).map(|value| entry_weights.find(value)).collect();
+        assert_eq!(found, [0, 0, 0, 2, 2, 2, 2, 2, 3, 3]);
+    }
+
+    #[test]
+    fn refunds_take_the_referral_fee_back() {
+        let lottery = Pubkey::new_unique();
+        let referrer = Pubkey::new_unique();
+        let ticket = Ticket {
+            lottery,
+            mint: Pubkey::new_unique(),
+            index: 0,
+            price_paid: 10_000,
+            referral_fee: 250,
+            referrer,
+            bump: 255,
+        };
+        let mut referral = Referral { lottery, referrer, accrued: 400, bump: 255 };
+
+        // the pot repays the principal and the referrer the fee, the full price in total
+        let fee = referral.take_back_fee(&ticket).unwrap();
+        assert_eq!(ticket.principal() + fee, ticket.price_paid);
+        assert_eq!(referral.accrued, 150);
+
+        // another referrer's rewards, or rewards already taken back, can't pay for it
+        let mut other = Referral { lottery, referrer: Pubkey::new_unique(), accrued: 400, bump: 255 };
+        assert!(other.take_back_fee(&ticket).is_err());
+        assert!(referral.take_back_fee(&ticket).is_err());
+        assert_eq!(referral.accrued, 150);
+    }
+
+    #[test]
+    fn withdraws_the_full_price_of_referred_no_loss_tickets() {
+        let mut lottery = TokenLottery::from_v1_data(&v1_blob(&v1_lottery())).unwrap();
+        lottery.referral_bps = 250;
+        lottery.check_referrals().unwrap();
+        assert_eq!(lottery.referral_fee(lottery.price).unwrap(), 250);
+
+        // turning on no-loss mode, in either order, refuses a referral share
+        lottery.is_no_loss = true;
+        assert!(lottery.check_referrals().is_err());
+        lottery.referral_bps = 0;
+        lottery.check_referrals().unwrap();
+
+        // so a ticket bought through a referrer puts its whole price into
+        // the pot, which withdraw_principal pays back
+        let fee = lottery.referral_fee(lottery.price).unwrap();
+        let ticket = Ticket {
+            lottery: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            index: 0,
+            price_paid: lottery.price,
+            referral_fee: fee,
+            referrer: Pubkey::new_unique(),
+            bump: 255,
+        };
+        assert_eq!(fee, 0);
+        assert_eq!(ticket.principal(), lottery.price);
+    }
+
+    #[test]
+    fn prices_tickets_along_the_bonding_curve() {
+        assert_eq!(BondingCurve::Flat.price_at(10_000, 0).unwrap(), 10_000);
+        assert_eq!(BondingCurve::Flat.price_at(10_000, u64::MAX).unwrap(), 10_000);
+
+        let linear = BondingCurve::Linear { step: 100 };
+        assert_eq!(linear.price_at(10_000, 0).unwrap(), 10_000);
+        assert_eq!(linear.price_at(10_000, 5).unwrap(), 10_500);
+
+        // 10% growth per ticket sold, compounded
+        let exponential = BondingCurve::Exponential { growth_bps: 1_000 };
+        assert_eq!(exponential.price_at(10_000, 0).unwrap(), 10_000);
+        assert_eq!(exponential.price_at(10_000, 1).unwrap(), 11_000);
+        assert_eq!(exponential.price_at(10_000, 2).unwrap(), 12_100);
+        assert_eq!(exponential.price_at(10_000, 3).unwrap(), 13_310);
+        assert_eq!(BondingCurve::Exponential { growth_bps: 0 }.price_at(10_000, 1_000).unwrap(), 10_000);
+    }
+
+    #[test]
+    fn rejects_bonding_curve_prices_past_u64() {
+        let linear = BondingCurve::Linear { step: u64::MAX };
+        assert_eq!(linear.price_at(0, 1).unwrap(), u64::MAX);
+        assert!(linear.price_at(1, 1).is_err());
+        assert!(linear.price_at(0, 2).is_err());
+
+        let exponential = BondingCurve::Exponential { growth_bps: u16::MAX };
+        assert!(exponential.price_at(10_000, 64).is_err());
+        assert!(exponential.price_at(u64::MAX, 1).is_err());
+        assert!(exponential.price_at(10_000, u64::MAX).is_err());
+    }
+
+    fn hash_pair(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
+        if a <= b {
+            keccak::hashv(&[&a, &b]).to_bytes()
+        } else {
+            keccak::hashv(&[&b, &a]).to_bytes()
+        }
+    }
+
+    #[test]
+    fn verifies_allowlist_merkle_proofs() {
+        // leaves as `buy_ticket_allowlisted` hashes them, for four buyers
+        let leaves: Vec<[u8; 32]> = (0..4u64)
+            .map(|max_allowance| keccak::hashv(&[Pubkey::new_unique().as_ref(), &max_allowance.to_le_bytes()]).to_bytes())
+            .collect();
+        let (left, right) = (hash_pair(leaves[0], leaves[1]), hash_pair(leaves[2], leaves[3]));
+        let root = hash_pair(left, right);
+
+        assert!(verify_merkle_proof(&[leaves[1], right], root, leaves[0]));
+        assert!(verify_merkle_proof(&[leaves[2], left], root, leaves[3]));
+        // siblings are sorted before hashing, so their order in the tree doesn't matter
+        assert!(verify_merkle_proof(&[leaves[0], right], root, leaves[1]));
+
+        // a leaf that isn't in the tree, or the right leaf with another proof
+        let outsider = keccak::hashv(&[Pubkey::new_unique().as_ref(), &1u64.to_le_bytes()]).to_bytes();
+        assert!(!verify_merkle_proof(&[leaves[1], right], root, outsider));
+        assert!(!verify_merkle_proof(&[leaves[2], right], root, leaves[0]));
+
+        // proofs one level short or one level too deep
+        assert!(!verify_merkle_proof(&[leaves[1]], root, leaves[0]));
+        assert!(!verify_merkle_proof(&[leaves[1], right, leaves[2]], root, leaves[0]));
+        assert!(!verify_merkle_proof(&[], root, leaves[0]));
+    }
+
+    fn gate_token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> TokenAccount {
+        use anchor_lang::solana_program::program_pack::Pack;
+        use anchor_spl::token_2022::spl_token_2022::state::{Account, AccountState};
+
+        let account = Account { mint, owner, amount, state: AccountState::Initialized, ..Default::default() };
+        let mut data = vec![0; Account::LEN];
+        account.pack_into_slice(&mut data);
+        TokenAccount::try_deserialize(&mut &data[..]).unwrap()
+    }
+
+    fn gate_metadata(mint: Pubkey, collection: Option<(Pubkey, bool)>) -> MetadataAccount {
+        named_metadata(mint, "Gate", collection)
+    }
+
+    fn named_metadata(mint: Pubkey, name: &str, collection: Option<(Pubkey, bool)>) -> MetadataAccount {
+        use anchor_spl::metadata::mpl_token_metadata::{accounts::Metadata, types::{Collection, Key}};
+
+        let metadata = Metadata {
+            key: Key::MetadataV1,
+            update_authority: Pubkey::new_unique(),
+            mint,
+            name: name.to_owned(),
+            symbol: String::new(),
+            uri: String::new(),
+            seller_fee_basis_points: 0,
+            creators: None,
+            primary_sale_happened: false,
+            is_mutable: true,
+            edition_nonce: None,
+            token_standard: None,
+            collection: collection.map(|(key, verified)| Collection { verified, key }),
+            uses: None,
+            collection_details: None,
+            programmable_config: None,
+        };
+        MetadataAccount::try_deserialize(&mut &metadata.try_to_vec().unwrap()[..]).unwrap()
+    }
+
+    #[test]
+    fn checks_purchase_gates() {
+        let buyer = Pubkey::new_unique();
+        let mut lottery = TokenLottery::from_v1_data(&v1_blob(&v1_lottery())).unwrap();
+
+        // no gate, nothing to show
+        assert!(check_gate(&lottery, &buyer, None, None).is_ok());
+
+        let mint = Pubkey::new_unique();
+        lottery.purchase_gate = PurchaseGate::TokenBalance { mint, min_amount: 5 };
+        assert!(check_gate(&lottery, &buyer, Some(&gate_token_account(mint, buyer, 5)), None).is_ok());
+        assert!(check_gate(&lottery, &buyer, None, None).is_err());
+        assert!(check_gate(&lottery, &buyer, Some(&gate_token_account(mint, buyer, 4)), None).is_err());
+        assert!(check_gate(&lottery, &buyer, Some(&gate_token_account(Pubkey::new_unique(), buyer, 5)), None).is_err());
+        assert!(check_gate(&lottery, &buyer, Some(&gate_token_account(mint, Pubkey::new_unique(), 5)), None).is_err());
+
+        let collection_mint = Pubkey::new_unique();
+        let nft = Pubkey::new_unique();
+        lottery.purchase_gate = PurchaseGate::Collection { collection_mint };
+        let held = gate_token_account(nft, buyer, 1);
+        let verified = gate_metadata(nft, Some((collection_mint, true)));
+        assert!(check_gate(&lottery, &buyer, Some(&held), Some(&verified)).is_ok());
+        assert!(check_gate(&lottery, &buyer, Some(&held), None).is_err());
+        assert!(check_gate(&lottery, &buyer, Some(&gate_token_account(nft, buyer, 0)), Some(&verified)).is_err());
+        assert!(check_gate(&lottery, &buyer, Some(&gate_token_account(nft, Pubkey::new_unique(), 1)), Some(&verified)).is_err());
+        // metadata of another NFT, unverified or of another collection
+        let other = gate_metadata(Pubkey::new_unique(), Some((collection_mint, true)));
+        assert!(check_gate(&lottery, &buyer, Some(&held), Some(&other)).is_err());
+        let unverified = gate_metadata(nft, Some((collection_mint, false)));
+        assert!(check_gate(&lottery, &buyer, Some(&held), Some(&unverified)).is_err());
+        let foreign = gate_metadata(nft, Some((Pubkey::new_unique(), true)));
+        assert!(check_gate(&lottery, &buyer, Some(&held), Some(&foreign)).is_err());
+        assert!(check_gate(&lottery, &buyer, Some(&held), Some(&gate_metadata(nft, None))).is_err());
+    }
+}
@@ -25,8 +25,12 @@ describe('tokenlottery', () => {
   
   const TOKEN_METADATA_PROGRAM_ID = new anchor.web3.PublicKey('metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s');
   
+  // lottery ids are assigned by the registry, set once the config is created
+  let token_lottery_id = new anchor.BN(0);
   const idBuffer = Buffer.alloc(8); // Assuming id is u64
-  idBuffer.writeBigUInt64LE(BigInt(17)); // Convert properly
+
+  // compute budget buy_ticket has to stay within
+  const BUY_TICKET_COMPUTE_UNITS = 200000;
   
   async function buyTicket(){
 
@@ -41,14 +45,61 @@ describe('tokenlottery', () => {
   
     console.log(token_lottery.toBase58());
 
-    const buyTicketTx = await program.methods.buyTicket().accounts({
+    // buy_ticket leaves the Metaplex accounts to Metaplex, they are passed in
+    const { numberOfTickets } = await program.account.tokenLottery.fetch(token_lottery);
+    const ticketMint = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('ticket_mint'),
+        wallet.payer.publicKey.toBuffer(),
+        idBuffer,
+        numberOfTickets.toArrayLike(Buffer, 'le', 8),
+      ],
+      program.programId
+    )[0];
+
+    const collectionMint = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('collection_mint'),
+        wallet.payer.publicKey.toBuffer(),
+        idBuffer
+      ],
+      program.programId
+    )[0];
+
+    const metadataOf = (mint: anchor.web3.PublicKey) => anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('metadata'),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        mint.toBuffer(),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    )[0];
+
+    const masterEditionOf = (mint: anchor.web3.PublicKey) => anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('metadata'),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        mint.toBuffer(),
+        Buffer.from('edition'),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    )[0];
+
+    const buyTicketTx = await program.methods.buyTicket(new anchor.BN(10000)).accounts({
+      payer: wallet.payer.publicKey,
+      buyer: wallet.payer.publicKey,
+      recipient: wallet.payer.publicKey,
       tokenProgram: TOKEN_PROGRAM_ID,
-      tokenLottery: token_lottery
+      tokenLottery: token_lottery,
+      metadata: metadataOf(ticketMint),
+      masterEdition: masterEditionOf(ticketMint),
+      collectionMetadata: metadataOf(collectionMint),
+      collectionMasterEdition: masterEditionOf(collectionMint),
     })
     .instruction();
 
     const computeIx = anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
-      units: 300000
+      units: BUY_TICKET_COMPUTE_UNITS
     });
 
     const priorityIx = anchor.web3.ComputeBudgetProgram.setComputeUnitPrice({
@@ -74,6 +125,21 @@ describe('tokenlottery', () => {
 
       console.log("buy ticket", sig);
 
+      const confirmed = await connection.getTransaction(sig, {
+        commitment: 'confirmed',
+        maxSupportedTransactionVersion: 0,
+      });
+      expect(confirmed?.meta?.err).toBeNull();
+
+      // units buy_ticket itself consumed, Metaplex CPIs included
+      const consumedLog = confirmed?.meta?.logMessages?.find((line) =>
+        line.startsWith(`Program ${program.programId.toBase58()} consumed`)
+      );
+      const consumed = Number(consumedLog?.match(/consumed (\d+) of/)?.[1]);
+      console.log("buy ticket compute units", consumed);
+      expect(consumed).toBeGreaterThan(0);
+      expect(consumed).toBeLessThanOrEqual(BUY_TICKET_COMPUTE_UNITS);
+
   }
 
   beforeAll(async()=>{
@@ -87,6 +153,15 @@ describe('tokenlottery', () => {
     );
 
     switchboardProgram = new anchor.Program(switchboardIDL!, provider);
+
+    const registry = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from('registry')],
+      program.programId
+    )[0];
+
+    if (!(await connection.getAccountInfo(registry))) {
+      await program.methods.initializeRegistry().rpc();
+    }
   });
 
   it('Is initialized!', async ()=>{
@@ -94,15 +169,19 @@ describe('tokenlottery', () => {
     const slot = await connection.getSlot();
     console.log('Current slot:', slot);
 
-    const token_lottery_id = new anchor.BN(17
-      
-    );
+    const registry = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from('registry')],
+      program.programId
+    )[0];
+
+    token_lottery_id = (await program.account.registry.fetch(registry)).lotteryCount;
+    idBuffer.writeBigUInt64LE(BigInt(token_lottery_id.toString()));
 
     const initConfigTx = await program.methods.initializeConfig(
-      token_lottery_id,
       new anchor.BN(0),
       new anchor.BN(slot + 20),
-      new anchor.BN(10000)
+      new anchor.BN(10000),
+      new anchor.BN(1)
     ).instruction();
 
     const mint = anchor.web3.PublicKey.findProgramAddressSync(
@@ -172,6 +251,30 @@ describe('tokenlottery', () => {
 
   })
 
+  it('Is funding the pot', async()=>{
+
+    const token_lottery = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('token_lottery'),
+        wallet.payer.publicKey.toBuffer(),
+        idBuffer
+      ],
+      program.programId
+    )[0];
+
+    const before = await program.account.tokenLottery.fetch(token_lottery);
+
+    const sig = await program.methods.fundPot(new anchor.BN(50000)).accounts({
+      tokenLottery: token_lottery
+    }).rpc({skipPreflight: true});
+
+    console.log("fund pot", sig);
+
+    const after = await program.account.tokenLottery.fetch(token_lottery);
+    expect(after.sponsorAmount.toNumber()).toEqual(before.sponsorAmount.toNumber() + 50000);
+    expect(after.lotteryPotAmount.toNumber()).toEqual(before.lotteryPotAmount.toNumber() + 50000);
+  })
+
   it('Is committing and revealing a winner', async()=>{
 
     const token_lottery = anchor.web3.PublicKey.findProgramAddressSync(
@@ -232,6 +335,12 @@ describe('tokenlottery', () => {
     );
     
   
+    // the draw can only be committed once sales are over
+    const lotteryEnd = (await program.account.tokenLottery.fetch(token_lottery)).lotteryEnd.toNumber();
+    while (await connection.getSlot() <= lotteryEnd) {
+      await new Promise((resolve) => setTimeout(resolve, 400));
+    }
+
     // return instruction for this task
     const sbCommitTx = await randomness.commitIx(queueAccount.pubkey);
     
@@ -327,7 +436,7 @@ describe('tokenlottery', () => {
       [
         Buffer.from('ticket_mint'),
         wallet.publicKey.toBuffer(),
-        new anchor.BN(17).toArrayLike(Buffer),
+        idBuffer,
         lotteryConfig.winner.toArrayLike(Buffer),
       ],
       TOKEN_PROGRAM_ID
@@ -346,10 +455,21 @@ describe('tokenlottery', () => {
     
 
    
+    const winningMasterEdition = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('metadata'),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        winningMint.toBuffer(),
+        Buffer.from('edition'),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    )[0];
+
     const claimIx = await program.methods.claimPrize().accounts({
       tokenProgram: TOKEN_PROGRAM_ID,
       tokenLottery: token_lottery,
       ticketMint: winningMint,
+      masterEdition: winningMasterEdition,
     }).instruction();
 
     const blockhashContext = await connection.getLatestBlockhashAndContext();
@@ -369,5 +489,79 @@ describe('tokenlottery', () => {
 
     console.log("Claim signature", claimSignature);
   })
+
+  it("Is burning a losing ticket", async()=>{
+
+    const token_lottery = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('token_lottery'),
+        wallet.payer.publicKey.toBuffer(),
+        idBuffer
+      ],
+      program.programId
+    )[0];
+
+    const lotteryConfig = await program.account.tokenLottery.fetch(token_lottery);
+    const losingIndex = lotteryConfig.winner.eqn(0) ? new anchor.BN(1) : new anchor.BN(0);
+
+    const losingMint = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('ticket_mint'),
+        wallet.publicKey.toBuffer(),
+        idBuffer,
+        losingIndex.toArrayLike(Buffer, 'le', 8),
+      ],
+      program.programId
+    )[0];
+
+    const collectionMint = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('collection_mint'),
+        wallet.payer.publicKey.toBuffer(),
+        idBuffer
+      ],
+      program.programId
+    )[0];
+
+    const masterEdition = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('metadata'),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        losingMint.toBuffer(),
+        Buffer.from('edition'),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    )[0];
+
+    const collectionMetadata = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('metadata'),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        collectionMint.toBuffer(),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    )[0];
+
+    const holderTokenAccount = getAssociatedTokenAddressSync(
+      losingMint,
+      wallet.publicKey
+    );
+
+    const balanceBefore = await connection.getBalance(wallet.publicKey);
+
+    const burnSignature = await program.methods.burnTicket().accounts({
+      tokenLottery: token_lottery,
+      ticketMint: losingMint,
+      holderTokenAccount,
+      masterEdition,
+      collectionMetadata,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    }).rpc();
+
+    console.log("Burn signature", burnSignature);
+
+    expect(await connection.getAccountInfo(holderTokenAccount)).toBeNull();
+    expect(await connection.getBalance(wallet.publicKey)).toBeGreaterThan(balanceBefore);
+  })
   
 })